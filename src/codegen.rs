@@ -1,23 +1,177 @@
-use crate::parser::{BoxExpression, ExpressionType};
+use std::convert::TryFrom;
+use crate::parser::{Expr, Stmt};
+use crate::tokens::{SharedStr, Span, TokenType};
+
+fn is_comparison(operator: TokenType) -> bool {
+    matches!(
+        operator,
+        TokenType::Equals | TokenType::Unequal | TokenType::Lt | TokenType::Gt | TokenType::Lteq | TokenType::Gteq
+    )
+}
+
+fn is_logical(operator: TokenType) -> bool {
+    matches!(operator, TokenType::And | TokenType::Or)
+}
+
+/// The expression inside any parentheses around `expression`.
+fn ungrouped(expression: &Expr) -> &Expr {
+    match expression {
+        Expr::Grouping { expr, .. } => ungrouped(expr),
+        _ => expression,
+    }
+}
+
+/// Whether an expression evaluates to a boolean, which is printed as
+/// `true` or `false` rather than as 0 or 1.
+fn is_boolean(expression: &Expr) -> bool {
+    match ungrouped(expression) {
+        Expr::Boolean { .. } => true,
+        Expr::Prefix { operator, .. } => *operator == TokenType::Bang,
+        Expr::Infix { operator, .. } => is_comparison(*operator) || is_logical(*operator),
+        _ => false,
+    }
+}
+
+/// The value of a number, char or boolean literal as an integer. Negated
+/// and complemented literals are constants too.
+fn integer_literal(expression: &Expr) -> Option<i64> {
+    match ungrouped(expression) {
+        Expr::Number { value, .. } => Some(*value),
+        Expr::Char { value, .. } => Some(*value as i64),
+        Expr::Boolean { value, .. } => Some(*value as i64),
+        Expr::Prefix { operator: TokenType::Minus, right, .. } => integer_literal(right).map(i64::wrapping_neg),
+        Expr::Prefix { operator: TokenType::Tilde, right, .. } => integer_literal(right).map(|value| !value),
+        _ => None,
+    }
+}
+
+/// The value of a literal as an instruction operand, if it fits in the
+/// sign-extended 32 bits that instructions take.
+fn immediate(expression: &Expr) -> Option<String> {
+    let value = i32::try_from(integer_literal(expression)?).ok()?;
+    Some(value.to_string())
+}
+
+/// Registers holding the first six arguments of a call, as in the System V
+/// x86_64 calling convention. Further arguments are passed on the stack,
+/// rsp is 16-byte aligned at every call, and functions preserve rbx and rbp.
+const ARGUMENT_REGISTERS: [&str; 6] = ["rdi", "rsi", "rdx", "rcx", "r8", "r9"];
+
+/// Label of the routine for the function `name`, kept apart from the
+/// compiler's own labels. Assemblers only take ASCII labels, so any other
+/// character is written as `_u` and its UTF-16 code units in hex, and `_`
+/// is doubled so that no two names give the same label.
+fn function_label(name: &str) -> String {
+    let mut label = String::from("fn_");
+    for c in name.chars() {
+        match c {
+            '_' => label.push_str("__"),
+            c if c.is_ascii_alphanumeric() => label.push(c),
+            c => {
+                for unit in c.encode_utf16(&mut [0; 2]) {
+                    label.push_str(&format!("_u{:04x}", unit));
+                }
+            }
+        }
+    }
+    label
+}
+
+/// A piece of a print statement: either text known at compile time or an
+/// expression whose value is printed as it is computed.
+enum PrintPiece<'a> {
+    Text(String),
+    Value(&'a Expr),
+}
+
+/// A variable, which holds either an integer or the bits of a float.
+struct Local {
+    name: SharedStr,
+    is_float: bool,
+}
 
 struct Compiler {
     code: String,
-    expressions: Vec<BoxExpression>,
+    statements: Vec<Stmt>,
+    /// Constant strings, emitted in the data section as `str_<index>`.
+    strings: Vec<String>,
+    uses_print_int: bool,
+    uses_print_bool: bool,
+    uses_print_float: bool,
+    /// Name and number of parameters of every function in the program.
+    functions: Vec<(SharedStr, usize)>,
+    /// Whether the code being emitted is inside a function, where `return`
+    /// gives back a value rather than exiting the program.
+    in_function: bool,
+    /// Variables in scope, in order of declaration. The variable at index
+    /// `i` lives at `[rbp - 8 * (i + 1)]`. A variable is a float if the
+    /// value it was declared with is one.
+    locals: Vec<Local>,
+    /// Most variables in scope at once in the current frame, which is how
+    /// many slots it needs.
+    frame_slots: usize,
+    /// Bytes pushed below the current frame for values being computed, which
+    /// calls pad to keep rsp 16-byte aligned.
+    stack_depth: usize,
+    /// Number of labels made up so far, which keeps each of them unique.
+    labels: usize,
 }
 
 impl Compiler {
-    fn new(expressions: Vec<BoxExpression>) -> Compiler {
+    fn new(statements: Vec<Stmt>) -> Compiler {
         Compiler {
             code: String::from(""),
-            expressions: expressions,
+            statements,
+            strings: Vec::new(),
+            uses_print_int: false,
+            uses_print_bool: false,
+            uses_print_float: false,
+            functions: Vec::new(),
+            in_function: false,
+            locals: Vec::new(),
+            frame_slots: 0,
+            stack_depth: 0,
+            labels: 0,
         }
     }
 
     fn compile(&mut self) {
         self.build_prelude();
-        for expression in &self.expressions {
-            self.emit_code_for_expression(&expression);
+        let (functions, statements): (Vec<Stmt>, Vec<Stmt>) = std::mem::take(&mut self.statements)
+            .into_iter()
+            .partition(|statement| matches!(statement, Stmt::Function { .. }));
+        for function in &functions {
+            if let Stmt::Function { name, parameters, span, .. } = function {
+                if self.functions.iter().any(|(declared, _)| declared == name) {
+                    panic!("{}: function '{}' is already declared", span, name);
+                }
+                self.functions.push((name.clone(), parameters.len()));
+            }
+        }
+
+        let body_start = self.code.len();
+        for statement in &statements {
+            self.emit_code_for_statement(statement);
+        }
+        self.build_frame(body_start);
+        if !matches!(statements.last(), Some(Stmt::Return { .. })) {
+            // The program's result is the one of main, if there is one.
+            if self.functions.iter().any(|(name, _)| name == "main") {
+                self.asm_write(&format!("call {}", function_label("main")));
+                self.asm_mov("rbx", "rax");
+            } else {
+                self.asm_mov("rbx", "0");
+            }
+            self.asm_mov("rax", "1");
+            self.asm_write("int 0x80");
+        }
+        for function in &functions {
+            if let Stmt::Function { name, parameters, body, .. } = function {
+                self.emit_code_for_function(name, parameters, body);
+            }
         }
+        self.build_routines();
+        self.build_data();
     }
 
     fn asm_write(&mut self, line: &str) {
@@ -38,100 +192,770 @@ impl Compiler {
         self.asm_write("_start:");
     }
 
-    fn emit_code_for_expression(&mut self, expression: &BoxExpression) {
-        match expression.get_type() {
-            ExpressionType::Number => todo!(),
-            ExpressionType::Infix => todo!(),
-            ExpressionType::String => todo!(),
-            ExpressionType::Identifier => todo!(),
-            ExpressionType::Prefix => todo!(),
-            ExpressionType::Return => {
-                self.emit_code_for_return(expression);
+    /// Sets up the stack frame holding the variables, before the code from
+    /// `body_start` on, now that the number of them is known.
+    fn build_frame(&mut self, body_start: usize) {
+        if self.frame_slots == 0 {
+            return;
+        }
+        self.reserve_locals(body_start);
+        self.code.insert_str(body_start, "push rbp\nmov rbp, rsp\n");
+    }
+
+    /// Makes room below rbp for the variables, at `at` in the code. What is
+    /// pushed before the frame, the saved rbp in `_start` and the return
+    /// address, rbx and rbp in functions, takes an odd number of 8-byte slots,
+    /// so an odd number of slots in the frame leaves rsp 16-byte aligned.
+    fn reserve_locals(&mut self, at: usize) {
+        let slots = self.frame_slots | 1;
+        self.code.insert_str(at, &format!("sub rsp, {}\n", 8 * slots));
+    }
+
+    /// Slot of the variable `name` most recently declared.
+    fn local_slot(&self, name: &str, span: &Span) -> String {
+        match self.locals.iter().rposition(|local| *local.name == *name) {
+            Some(index) => format!("[rbp - {}]", 8 * (index + 1)),
+            None => panic!("{}: unknown variable '{}'", span, name),
+        }
+    }
+
+    /// The variable `name` most recently declared, if there is one.
+    fn local(&self, name: &str) -> Option<&Local> {
+        self.locals.iter().rev().find(|local| *local.name == *name)
+    }
+
+    /// Declares a new variable, shadowing any other with the same name, and
+    /// gives its slot.
+    fn declare_local(&mut self, name: &SharedStr, is_float: bool) -> String {
+        self.locals.push(Local { name: name.clone(), is_float });
+        self.frame_slots = self.frame_slots.max(self.locals.len());
+        format!("[rbp - {}]", 8 * self.locals.len())
+    }
+
+    /// Whether an expression evaluates to a float. Arithmetic with a float on
+    /// either side is done in floating point; comparisons and logical operators
+    /// always give 0 or 1.
+    fn is_float(&self, expression: &Expr) -> bool {
+        match ungrouped(expression) {
+            Expr::Float { .. } => true,
+            Expr::Identifier { name, .. } => self.local(name).is_some_and(|local| local.is_float),
+            Expr::Prefix { operator: TokenType::Minus, right, .. } => self.is_float(right),
+            Expr::Infix { left, right, .. } if !is_boolean(expression) => self.is_float(left) || self.is_float(right),
+            _ => false,
+        }
+    }
+
+    /// Whether an expression compares two operands of which at least one is a float.
+    fn is_float_comparison(&self, expression: &Expr) -> bool {
+        match ungrouped(expression) {
+            Expr::Infix { operator, left, right, .. } => {
+                is_comparison(*operator) && (self.is_float(left) || self.is_float(right))
+            },
+            _ => false,
+        }
+    }
+
+    /// Emits the print helpers used by the program, which are called with
+    /// the value to print in rbx, or in xmm0 for floats.
+    fn build_routines(&mut self) {
+        if self.uses_print_int {
+            // Digits are written right to left below the stack pointer.
+            self.asm_write("print_int:");
+            self.asm_mov("rax", "rbx");
+            self.asm_write("lea rsi, [rsp - 1]");
+            self.asm_mov("rcx", "0");
+            self.asm_mov("r8", "10");
+            self.asm_write("test rax, rax");
+            self.asm_write("jns print_int_digit");
+            self.asm_write("neg rax");
+            self.asm_write("print_int_digit:");
+            self.asm_mov("rdx", "0");
+            self.asm_write("div r8");
+            self.asm_write("add dl, 48");
+            self.asm_mov("[rsi]", "dl");
+            self.asm_write("dec rsi");
+            self.asm_write("inc rcx");
+            self.asm_write("test rax, rax");
+            self.asm_write("jnz print_int_digit");
+            self.asm_write("test rbx, rbx");
+            self.asm_write("jns print_int_write");
+            self.asm_mov("byte [rsi]", "45");
+            self.asm_write("dec rsi");
+            self.asm_write("inc rcx");
+            self.asm_write("print_int_write:");
+            self.asm_write("inc rsi");
+            self.asm_mov("rdx", "rcx");
+            self.asm_mov("rax", "1");
+            self.asm_mov("rdi", "1");
+            self.asm_write("syscall");
+            self.asm_write("ret");
+        }
+        if self.uses_print_bool {
+            let true_label = self.string_label("true");
+            let false_label = self.string_label("false");
+            self.asm_write("print_bool:");
+            self.asm_write(&format!("lea rsi, [rel {}]", true_label));
+            self.asm_mov("rdx", "4");
+            self.asm_write("test rbx, rbx");
+            self.asm_write("jnz print_bool_write");
+            self.asm_write(&format!("lea rsi, [rel {}]", false_label));
+            self.asm_mov("rdx", "5");
+            self.asm_write("print_bool_write:");
+            self.asm_mov("rax", "1");
+            self.asm_mov("rdi", "1");
+            self.asm_write("syscall");
+            self.asm_write("ret");
+        }
+        if self.uses_print_float {
+            // Rounded to six decimals, with the trailing zeros dropped but
+            // one. The integer part must fit in 64 bits, so magnitudes from
+            // 2^63 on are not printed right; NaN and infinities are spelled out.
+            let nan_label = self.string_label("NaN");
+            let infinity_label = self.string_label("-inf");
+            self.asm_write("print_float:");
+            self.asm_write("movq rax, xmm0");
+            self.asm_write("btr rax, 63");
+            self.asm_write("setc r9b");
+            self.asm_mov("rcx", "0x7ff0000000000000");
+            self.asm_write("cmp rax, rcx");
+            self.asm_write("ja print_float_nan");
+            self.asm_write("je print_float_infinity");
+            self.asm_write("movq xmm0, rax");
+            self.asm_write("cvttsd2si r10, xmm0");
+            self.asm_write("cvtsi2sd xmm1, r10");
+            self.asm_write("subsd xmm0, xmm1");
+            self.asm_mov("rcx", &format!("0x{:016x}", 1e6f64.to_bits()));
+            self.asm_write("movq xmm1, rcx");
+            self.asm_write("mulsd xmm0, xmm1");
+            self.asm_write("cvtsd2si rax, xmm0");
+            self.asm_write("cmp rax, 1000000");
+            self.asm_write("jne print_float_fraction");
+            self.asm_write("inc r10");
+            self.asm_mov("rax", "0");
+            self.asm_write("print_float_fraction:");
+            self.asm_write("lea rsi, [rsp - 1]");
+            self.asm_mov("rcx", "6");
+            self.asm_mov("r8", "10");
+            self.asm_write("print_float_trim:");
+            self.asm_write("cmp rcx, 1");
+            self.asm_write("je print_float_fraction_digit");
+            self.asm_mov("r11", "rax");
+            self.asm_mov("rdx", "0");
+            self.asm_write("div r8");
+            self.asm_write("test rdx, rdx");
+            self.asm_write("jnz print_float_trimmed");
+            self.asm_write("dec rcx");
+            self.asm_write("jmp print_float_trim");
+            self.asm_write("print_float_trimmed:");
+            self.asm_mov("rax", "r11");
+            self.asm_write("print_float_fraction_digit:");
+            self.asm_mov("rdx", "0");
+            self.asm_write("div r8");
+            self.asm_write("add dl, 48");
+            self.asm_mov("[rsi]", "dl");
+            self.asm_write("dec rsi");
+            self.asm_write("dec rcx");
+            self.asm_write("jnz print_float_fraction_digit");
+            self.asm_mov("byte [rsi]", "46");
+            self.asm_write("dec rsi");
+            self.asm_mov("rax", "r10");
+            self.asm_write("print_float_integer_digit:");
+            self.asm_mov("rdx", "0");
+            self.asm_write("div r8");
+            self.asm_write("add dl, 48");
+            self.asm_mov("[rsi]", "dl");
+            self.asm_write("dec rsi");
+            self.asm_write("test rax, rax");
+            self.asm_write("jnz print_float_integer_digit");
+            self.asm_write("test r9b, r9b");
+            self.asm_write("jz print_float_digits");
+            self.asm_mov("byte [rsi]", "45");
+            self.asm_write("dec rsi");
+            self.asm_write("print_float_digits:");
+            self.asm_write("lea rdx, [rsp - 1]");
+            self.asm_write("sub rdx, rsi");
+            self.asm_write("inc rsi");
+            self.asm_write("jmp print_float_write");
+            self.asm_write("print_float_nan:");
+            self.asm_write(&format!("lea rsi, [rel {}]", nan_label));
+            self.asm_mov("rdx", "3");
+            self.asm_write("jmp print_float_write");
+            self.asm_write("print_float_infinity:");
+            self.asm_write(&format!("lea rsi, [rel {}]", infinity_label));
+            self.asm_mov("rdx", "4");
+            self.asm_write("test r9b, r9b");
+            self.asm_write("jnz print_float_write");
+            self.asm_write("inc rsi");
+            self.asm_write("dec rdx");
+            self.asm_write("print_float_write:");
+            self.asm_mov("rax", "1");
+            self.asm_mov("rdi", "1");
+            self.asm_write("syscall");
+            self.asm_write("ret");
+        }
+    }
+
+    fn build_data(&mut self) {
+        if self.strings.is_empty() {
+            return;
+        }
+        self.asm_write("section .data");
+        let strings = std::mem::take(&mut self.strings);
+        for (index, string) in strings.iter().enumerate() {
+            let bytes: Vec<String> = string.bytes().map(|b| b.to_string()).collect();
+            self.asm_write(&format!("str_{}: db {}", index, bytes.join(", ")));
+        }
+    }
+
+    /// Label of a constant string in the data section, reusing an existing
+    /// one if the same string was already needed.
+    fn string_label(&mut self, string: &str) -> String {
+        let index = match self.strings.iter().position(|s| s == string) {
+            Some(index) => index,
+            None => {
+                self.strings.push(string.to_string());
+                self.strings.len() - 1
+            }
+        };
+        format!("str_{}", index)
+    }
+
+    /// A label for compiler-generated jumps that no other label shares.
+    fn unique_label(&mut self, name: &str) -> String {
+        self.labels += 1;
+        format!("{}_{}", name, self.labels - 1)
+    }
+
+    /// Emits the routine for a function. Its parameters are copied into
+    /// variables, and its value is returned in rax; falling off the end
+    /// returns 0. rbx is saved along with rbp, as the caller keeps it.
+    fn emit_code_for_function(&mut self, name: &str, parameters: &[SharedStr], body: &[Stmt]) {
+        let outer_locals = std::mem::take(&mut self.locals);
+        let outer_slots = std::mem::take(&mut self.frame_slots);
+        self.in_function = true;
+        self.asm_write(&format!("{}:", function_label(name)));
+        self.asm_write("push rbx");
+        self.asm_write("push rbp");
+        self.asm_mov("rbp", "rsp");
+        let frame_start = self.code.len();
+        for (index, parameter) in parameters.iter().enumerate() {
+            let slot = self.declare_local(parameter, false);
+            match ARGUMENT_REGISTERS.get(index) {
+                Some(register) => self.asm_mov(&slot, register),
+                None => {
+                    // Past the saved rbp and rbx and the return address.
+                    let offset = 24 + 8 * (index - ARGUMENT_REGISTERS.len());
+                    self.asm_mov("rax", &format!("[rbp + {}]", offset));
+                    self.asm_mov(&slot, "rax");
+                }
             }
         }
+        for statement in body {
+            self.emit_code_for_statement(statement);
+        }
+        if !matches!(body.last(), Some(Stmt::Return { .. })) {
+            self.asm_mov("rbx", "0");
+            self.emit_function_return();
+        }
+        self.reserve_locals(frame_start);
+        self.in_function = false;
+        self.locals = outer_locals;
+        self.frame_slots = outer_slots;
     }
 
-    fn emit_code_for_negation(&mut self, expression: &BoxExpression) {
-        let right_side = expression.right().unwrap();
-        match right_side.get_type() {
-            ExpressionType::Number => {
-                let val = if right_side.literal().unwrap() == "false" { 0 } else { 1 };
-                self.asm_write(format!("mov rbx, {}", val).as_str());
+    /// Calls a function and leaves its result in rbx. The arguments are
+    /// computed left to right into room made on the stack, then the first
+    /// six are popped into their registers and the rest stay where the
+    /// callee expects them. Padding above the arguments aligns rsp at the call.
+    fn emit_code_for_call(&mut self, name: &str, arguments: &[Expr], span: &Span) {
+        let parameters = match self.functions.iter().find(|(declared, _)| declared == name) {
+            Some((_, parameters)) => *parameters,
+            None => panic!("{}: unknown function '{}'", span, name),
+        };
+        if arguments.len() != parameters {
+            panic!("{}: '{}' takes {} arguments, found {}", span, name, parameters, arguments.len());
+        }
+
+        let on_stack = arguments.len().saturating_sub(ARGUMENT_REGISTERS.len());
+        let padding = (self.stack_depth + 8 * on_stack) % 16;
+        let room = 8 * arguments.len() + padding;
+        if room > 0 {
+            self.asm_write(&format!("sub rsp, {}", room));
+            self.stack_depth += room;
+        }
+        for (index, argument) in arguments.iter().enumerate() {
+            if self.is_float(argument) {
+                panic!("{}: functions take integers, found a float", argument.span());
+            }
+            self.emit_code_for_value(argument);
+            let slot = if index == 0 { "[rsp]".to_string() } else { format!("[rsp + {}]", 8 * index) };
+            self.asm_mov(&slot, "rbx");
+        }
+        for register in ARGUMENT_REGISTERS.iter().take(arguments.len()) {
+            self.asm_write(&format!("pop {}", register));
+        }
+        self.asm_write(&format!("call {}", function_label(name)));
+        let left = 8 * on_stack + padding;
+        if left > 0 {
+            self.asm_write(&format!("add rsp, {}", left));
+        }
+        self.stack_depth -= room;
+        self.asm_mov("rbx", "rax");
+    }
+
+    /// Returns from a function with the value in rbx.
+    fn emit_function_return(&mut self) {
+        self.asm_mov("rax", "rbx");
+        self.asm_mov("rsp", "rbp");
+        self.asm_write("pop rbp");
+        self.asm_write("pop rbx");
+        self.asm_write("ret");
+    }
+
+    fn emit_code_for_statement(&mut self, statement: &Stmt) {
+        match statement {
+            Stmt::Expression { expr } => self.emit_code_for_value(expr),
+            Stmt::Let { name, value, .. } => {
+                // The value is computed first, so it still sees any variable being shadowed.
+                let is_float = self.is_float(value);
+                self.emit_code_for_stored_value(value, is_float);
+                let slot = self.declare_local(name, is_float);
+                self.emit_code_for_store(&slot, is_float);
             },
-            ExpressionType::Infix => todo!(),
-            ExpressionType::String => todo!(),
-            ExpressionType::Identifier => todo!(),
-            ExpressionType::Prefix => {
-                self.emit_code_for_negation(right_side);
-                self.asm_write("xor rbx, 1");
+            Stmt::Assign { name, value, span } => {
+                let is_float = match self.local(name) {
+                    Some(local) if !local.is_float && self.is_float(value) => {
+                        panic!("{}: cannot assign a float to the integer variable '{}'", span, name)
+                    },
+                    Some(local) => local.is_float,
+                    None => self.is_float(value),
+                };
+                self.emit_code_for_stored_value(value, is_float);
+                let slot = match self.local(name) {
+                    Some(_) => self.local_slot(name, span),
+                    None => self.declare_local(name, is_float),
+                };
+                self.emit_code_for_store(&slot, is_float);
             },
-            ExpressionType::Return => panic!("can not prefix a return"),
+            Stmt::Return { value, .. } => self.emit_code_for_return(value),
+            Stmt::Print { value, .. } => self.emit_code_for_print(value),
+            Stmt::While { condition, body, .. } => self.emit_code_for_while(condition, body),
+            Stmt::Function { span, .. } => panic!("{}: functions can only be declared at the top level", span),
         }
     }
-    
-    fn emit_code_for_addition(&mut self, expression: &BoxExpression, first_iteration: bool) {
-        if let Some(left_side) = expression.left() {
-            match left_side.get_type() {
-                ExpressionType::Number => {
-                    let st = left_side.literal().unwrap();
-                    if first_iteration {
-                        self.asm_mov("rbx", &*st);
-                    } else {
-                        self.asm_add("rbx", &*st);
-                    }
+
+    /// Computes a value to be stored in a variable, into xmm0 for a float
+    /// variable and into rbx otherwise.
+    fn emit_code_for_stored_value(&mut self, value: &Expr, is_float: bool) {
+        if is_float {
+            self.emit_code_for_float(value);
+        } else {
+            self.emit_code_for_value(value);
+        }
+    }
+
+    /// Stores the value computed by `emit_code_for_stored_value` in `slot`.
+    fn emit_code_for_store(&mut self, slot: &str, is_float: bool) {
+        if is_float {
+            self.asm_write(&format!("movsd {}, xmm0", slot));
+        } else {
+            self.asm_mov(slot, "rbx");
+        }
+    }
+
+    /// Runs `body` for as long as `condition` is true, checking it before
+    /// every iteration. Its variables are dropped at the end of the body,
+    /// though their slots stay in the frame.
+    fn emit_code_for_while(&mut self, condition: &Expr, body: &[Stmt]) {
+        let start = self.unique_label("while_start");
+        let end = self.unique_label("while_end");
+        self.asm_write(&format!("{}:", start));
+        self.emit_code_for_negation(condition);
+        self.asm_write("test rbx, rbx");
+        self.asm_write(&format!("jnz {}", end));
+        let outer_locals = self.locals.len();
+        for statement in body {
+            self.emit_code_for_statement(statement);
+        }
+        self.locals.truncate(outer_locals);
+        self.asm_write(&format!("jmp {}", start));
+        self.asm_write(&format!("{}:", end));
+    }
+
+    /// Prints `value` followed by a newline. Literals are turned into text
+    /// at compile time and adjacent text is written at once; other
+    /// expressions are computed and printed at runtime.
+    fn emit_code_for_print(&mut self, value: &Expr) {
+        let parts = match value {
+            Expr::Interpolation { parts, .. } => parts.iter().collect(),
+            _ => vec![value],
+        };
+
+        let mut pieces: Vec<PrintPiece> = Vec::new();
+        for part in parts {
+            let text = match part {
+                Expr::String { value, .. } => value.clone(),
+                Expr::Number { value, .. } => value.to_string(),
+                Expr::Float { value, .. } => format!("{:?}", value),
+                Expr::Boolean { value, .. } => value.to_string(),
+                Expr::Char { value, .. } => value.to_string(),
+                _ => {
+                    pieces.push(PrintPiece::Value(part));
+                    continue;
                 },
-                ExpressionType::Infix => todo!(),
-                ExpressionType::String => todo!(),
-                ExpressionType::Identifier => todo!(),
-                ExpressionType::Prefix => todo!(),
-                ExpressionType::Return => panic!("can not prefix a return"),
+            };
+            match pieces.last_mut() {
+                Some(PrintPiece::Text(previous)) => previous.push_str(&text),
+                _ => pieces.push(PrintPiece::Text(text)),
             }
-        } else {
-            self.asm_add("rbx", &expression.literal().unwrap());
+        }
+        match pieces.last_mut() {
+            Some(PrintPiece::Text(previous)) => previous.push('\n'),
+            _ => pieces.push(PrintPiece::Text("\n".to_string())),
         }
 
-        if let Some(right_side) = expression.right() {
-            match right_side.get_type() {
-                ExpressionType::Number => {
-                    self.asm_add("rbx", &*right_side.literal().unwrap());
+        for piece in pieces {
+            match piece {
+                PrintPiece::Text(text) if text.is_empty() => {},
+                PrintPiece::Text(text) => {
+                    let label = self.string_label(&text);
+                    self.asm_mov("rax", "1");
+                    self.asm_mov("rdi", "1");
+                    self.asm_write(&format!("lea rsi, [rel {}]", label));
+                    self.asm_mov("rdx", &text.len().to_string());
+                    self.asm_write("syscall");
+                },
+                PrintPiece::Value(value) if self.is_float(value) => {
+                    self.emit_code_for_float(value);
+                    self.uses_print_float = true;
+                    self.asm_write("call print_float");
                 },
-                ExpressionType::Infix => {
-                    self.emit_code_for_addition(right_side.left().unwrap(), false);
-                    self.emit_code_for_addition(right_side.right().unwrap(), false);
+                PrintPiece::Value(value) => {
+                    self.emit_code_for_value(value);
+                    if is_boolean(value) {
+                        self.uses_print_bool = true;
+                        self.asm_write("call print_bool");
+                    } else {
+                        self.uses_print_int = true;
+                        self.asm_write("call print_int");
+                    }
                 },
-                ExpressionType::String => todo!(),
-                ExpressionType::Identifier => todo!(),
-                ExpressionType::Prefix => todo!(),
-                ExpressionType::Return => panic!("can not prefix a return"),
             }
         }
     }
 
-    fn emit_code_for_return(&mut self, expression: &BoxExpression) {
-        let right_side = expression.right().unwrap();
-        match right_side.get_type() {
-            ExpressionType::Number => {
-                self.asm_mov("rbx", &*right_side.literal().unwrap());
+    /// Leaves 1 in rbx if `right_side` is zero, 0 otherwise.
+    fn emit_code_for_negation(&mut self, right_side: &Expr) {
+        match right_side {
+            Expr::Number { value, .. } => {
+                let val = if *value == 0 { 1 } else { 0 };
+                self.asm_write(format!("mov rbx, {}", val).as_str());
+            },
+            Expr::Char { value, .. } => {
+                let val = if *value == '\0' { 1 } else { 0 };
+                self.asm_write(format!("mov rbx, {}", val).as_str());
+            },
+            Expr::Boolean { value, .. } => {
+                let val = if *value { 0 } else { 1 };
+                self.asm_write(format!("mov rbx, {}", val).as_str());
+            },
+            Expr::Float { value, .. } => {
+                let val = if *value == 0.0 { 1 } else { 0 };
+                self.asm_write(format!("mov rbx, {}", val).as_str());
+            },
+            Expr::Grouping { expr, .. } => self.emit_code_for_negation(expr),
+            Expr::Prefix { operator: TokenType::Bang, right, .. } => {
+                self.emit_code_for_negation(right);
+                self.asm_write("xor rbx, 1");
+            },
+            Expr::Infix { .. } | Expr::Prefix { .. } | Expr::Identifier { .. } if self.is_float(right_side) => {
+                self.emit_code_for_float(right_side);
+                self.asm_write("xorpd xmm1, xmm1");
+                self.asm_write("ucomisd xmm0, xmm1");
+                self.asm_write("sete al");
+                self.asm_write("setnp cl");
+                self.asm_write("and al, cl");
+                self.asm_write("movzx rbx, al");
+            },
+            Expr::Infix { .. } | Expr::Identifier { .. } | Expr::Call { .. } | Expr::Prefix { .. } => {
+                self.emit_code_for_value(right_side);
+                self.asm_write("cmp rbx, 0");
+                self.asm_write("sete al");
+                self.asm_write("movzx rbx, al");
+            },
+            Expr::String { span, .. } | Expr::Interpolation { span, .. } => {
+                panic!("{}: strings can only be printed", span)
+            },
+        }
+    }
+
+    /// Evaluates both operands of an integer operation, leaving the left one
+    /// in rbx, and gives the right one. A right operand that fits in an
+    /// instruction is used as an immediate; any other one is computed into
+    /// rcx while the left operand waits on the stack.
+    fn emit_code_for_operands(&mut self, left: &Expr, right: &Expr) -> String {
+        self.emit_code_for_value(left);
+        match immediate(right) {
+            Some(value) => value,
+            None => {
+                self.asm_write("push rbx");
+                self.stack_depth += 8;
+                self.emit_code_for_value(right);
+                self.asm_mov("rcx", "rbx");
+                self.asm_write("pop rbx");
+                self.stack_depth -= 8;
+                "rcx".to_string()
+            }
+        }
+    }
+
+    /// Computes an integer operation into rbx.
+    fn emit_code_for_arithmetic(&mut self, operator: TokenType, left: &Expr, right: &Expr, span: &Span) {
+        let rhs = self.emit_code_for_operands(left, right);
+        match operator {
+            TokenType::Plus => self.asm_add("rbx", &rhs),
+            TokenType::Minus => self.asm_write(&format!("sub rbx, {}", rhs)),
+            TokenType::Asterisk => self.asm_write(&format!("imul rbx, {}", rhs)),
+            TokenType::Ampersand => self.asm_write(&format!("and rbx, {}", rhs)),
+            TokenType::Pipe => self.asm_write(&format!("or rbx, {}", rhs)),
+            TokenType::Caret => self.asm_write(&format!("xor rbx, {}", rhs)),
+            TokenType::Lshift | TokenType::Rshift => {
+                let instruction = if operator == TokenType::Lshift { "sal" } else { "sar" };
+                let count = if rhs == "rcx" { "cl" } else { &rhs };
+                self.asm_write(&format!("{} rbx, {}", instruction, count));
+            },
+            TokenType::Slash | TokenType::Percent => {
+                if rhs != "rcx" {
+                    self.asm_mov("rcx", &rhs);
+                }
+                self.asm_mov("rax", "rbx");
+                self.asm_write("cqo");
+                self.asm_write("idiv rcx");
+                let result = if operator == TokenType::Slash { "rax" } else { "rdx" };
+                self.asm_mov("rbx", result);
+            },
+            operator => panic!("{}: '{}' is not an arithmetic operator", span, operator.operator_text()),
+        }
+    }
+
+    /// Leaves 1 in rbx if `left && right` or `left || right` holds, 0
+    /// otherwise. The right operand is only evaluated if the left one does
+    /// not already decide the result.
+    fn emit_code_for_logical(&mut self, operator: TokenType, left: &Expr, right: &Expr, span: &Span) {
+        let (name, jump) = match operator {
+            TokenType::And => ("and_end", "jz"),
+            TokenType::Or => ("or_end", "jnz"),
+            operator => panic!("{}: '{}' is not a logical operator", span, operator.operator_text()),
+        };
+        let end = self.unique_label(name);
+        self.emit_code_for_negation(left);
+        self.asm_write("xor rbx, 1");
+        self.asm_write("test rbx, rbx");
+        self.asm_write(&format!("{} {}", jump, end));
+        self.emit_code_for_negation(right);
+        self.asm_write("xor rbx, 1");
+        self.asm_write(&format!("{}:", end));
+    }
+
+    /// Compares two integers and leaves 1 in rbx if the comparison holds, 0
+    /// otherwise. The comparison is signed.
+    fn emit_code_for_comparison(&mut self, operator: TokenType, left: &Expr, right: &Expr, span: &Span) {
+        let rhs = self.emit_code_for_operands(left, right);
+        let instruction = match operator {
+            TokenType::Equals => "sete",
+            TokenType::Unequal => "setne",
+            TokenType::Lt => "setl",
+            TokenType::Gt => "setg",
+            TokenType::Lteq => "setle",
+            TokenType::Gteq => "setge",
+            operator => panic!("{}: '{}' is not a comparison", span, operator.operator_text()),
+        };
+        self.asm_write(&format!("cmp rbx, {}", rhs));
+        self.asm_write(&format!("{} al", instruction));
+        self.asm_write("movzx rbx, al");
+    }
+
+    /// Leaves the value of `expression` in xmm0. Integer operands are
+    /// converted, and xmm1 holds the right operand of binary operators.
+    fn emit_code_for_float(&mut self, expression: &Expr) {
+        match expression {
+            Expr::Float { value, .. } => {
+                self.asm_mov("rax", &format!("0x{:016x}", value.to_bits()));
+                self.asm_write("movq xmm0, rax");
+            },
+            Expr::Number { .. } | Expr::Char { .. } | Expr::Boolean { .. } => {
+                self.asm_mov("rax", &integer_literal(expression).unwrap().to_string());
+                self.asm_write("cvtsi2sd xmm0, rax");
+            },
+            Expr::Infix { .. } if !self.is_float(expression) => {
+                self.emit_code_for_value(expression);
+                self.asm_write("cvtsi2sd xmm0, rbx");
+            },
+            Expr::Infix { operator, left, right, span } => {
+                self.emit_code_for_float_operands(left, right);
+                let instruction = match operator {
+                    TokenType::Plus => "addsd",
+                    TokenType::Minus => "subsd",
+                    TokenType::Asterisk => "mulsd",
+                    TokenType::Slash => "divsd",
+                    operator => panic!("{}: '{}' is not supported on floats", span, operator.operator_text()),
+                };
+                self.asm_write(&format!("{} xmm0, xmm1", instruction));
+            },
+            Expr::Grouping { expr, .. } => self.emit_code_for_float(expr),
+            Expr::Prefix { .. } if !self.is_float(expression) => {
+                self.emit_code_for_value(expression);
+                self.asm_write("cvtsi2sd xmm0, rbx");
+            },
+            // Flips the sign bit, which also turns 0.0 into -0.0.
+            Expr::Prefix { operator: TokenType::Minus, right, .. } => {
+                self.emit_code_for_float(right);
+                self.asm_mov("rax", "0x8000000000000000");
+                self.asm_write("movq xmm1, rax");
+                self.asm_write("xorpd xmm0, xmm1");
+            },
+            Expr::Prefix { .. } => unreachable!("only '-' keeps a float a float"),
+            Expr::String { span, .. } | Expr::Interpolation { span, .. } => {
+                panic!("{}: strings can only be printed", span)
+            },
+            Expr::Identifier { name, span } if self.is_float(expression) => {
+                let slot = self.local_slot(name, span);
+                self.asm_write(&format!("movsd xmm0, {}", slot));
+            },
+            Expr::Identifier { .. } | Expr::Call { .. } => {
+                self.emit_code_for_value(expression);
+                self.asm_write("cvtsi2sd xmm0, rbx");
+            },
+        }
+    }
+
+    /// Evaluates both sides of a binary float operation, leaving the left one
+    /// in xmm0 and the right one in xmm1. The left value waits on the stack.
+    fn emit_code_for_float_operands(&mut self, left: &Expr, right: &Expr) {
+        self.emit_code_for_float(left);
+        self.asm_write("sub rsp, 8");
+        self.stack_depth += 8;
+        self.asm_write("movsd [rsp], xmm0");
+        self.emit_code_for_float(right);
+        self.asm_write("movsd xmm1, xmm0");
+        self.asm_write("movsd xmm0, [rsp]");
+        self.asm_write("add rsp, 8");
+        self.stack_depth -= 8;
+    }
+
+    /// Compares two floats and leaves 1 in rbx if the comparison holds, 0
+    /// otherwise. Comparisons involving NaN only hold for `!=`.
+    fn emit_code_for_float_comparison(&mut self, operator: TokenType, left: &Expr, right: &Expr, span: &Span) {
+        self.emit_code_for_float_operands(left, right);
+        match operator {
+            TokenType::Equals => {
+                self.asm_write("ucomisd xmm0, xmm1");
+                self.asm_write("sete al");
+                self.asm_write("setnp cl");
+                self.asm_write("and al, cl");
+            },
+            TokenType::Unequal => {
+                self.asm_write("ucomisd xmm0, xmm1");
+                self.asm_write("setne al");
+                self.asm_write("setp cl");
+                self.asm_write("or al, cl");
+            },
+            // Unordered results set CF, so above/above-or-equal are false for NaN.
+            TokenType::Gt => {
+                self.asm_write("ucomisd xmm0, xmm1");
+                self.asm_write("seta al");
+            },
+            TokenType::Gteq => {
+                self.asm_write("ucomisd xmm0, xmm1");
+                self.asm_write("setae al");
+            },
+            TokenType::Lt => {
+                self.asm_write("ucomisd xmm1, xmm0");
+                self.asm_write("seta al");
+            },
+            TokenType::Lteq => {
+                self.asm_write("ucomisd xmm1, xmm0");
+                self.asm_write("setae al");
+            },
+            operator => panic!("{}: '{}' is not a comparison", span, operator.operator_text()),
+        }
+        self.asm_write("movzx rbx, al");
+    }
+
+    fn emit_code_for_return(&mut self, value: &Expr) {
+        if self.in_function && self.is_float(value) {
+            panic!("{}: functions return integers, found a float", value.span());
+        }
+        self.emit_code_for_value(value);
+        if self.in_function {
+            self.emit_function_return();
+        } else {
+            self.asm_mov("rax", "1");
+            self.asm_write("int 0x80");
+        }
+    }
+
+    /// Leaves the value of `right_side` in rbx. Floats are truncated.
+    fn emit_code_for_value(&mut self, right_side: &Expr) {
+        match right_side {
+            Expr::Number { .. } | Expr::Char { .. } | Expr::Boolean { .. } => {
+                self.asm_mov("rbx", &integer_literal(right_side).unwrap().to_string());
+            },
+            Expr::Float { .. } => {
+                self.emit_code_for_float(right_side);
+                self.asm_write("cvttsd2si rbx, xmm0");
+            },
+            Expr::Infix { .. } | Expr::Prefix { .. } | Expr::Identifier { .. } if self.is_float(right_side) => {
+                self.emit_code_for_float(right_side);
+                self.asm_write("cvttsd2si rbx, xmm0");
+            },
+            Expr::Infix { operator, left, right, span } if is_logical(*operator) => {
+                self.emit_code_for_logical(*operator, left, right, span);
+            },
+            Expr::Infix { operator, left, right, span } if self.is_float_comparison(right_side) => {
+                self.emit_code_for_float_comparison(*operator, left, right, span);
+            },
+            Expr::Infix { operator, left, right, span } if is_comparison(*operator) => {
+                self.emit_code_for_comparison(*operator, left, right, span);
+            },
+            Expr::Infix { operator, left, right, span } => {
+                self.emit_code_for_arithmetic(*operator, left, right, span);
+            },
+            Expr::Grouping { expr, .. } => self.emit_code_for_value(expr),
+            Expr::String { span, .. } | Expr::Interpolation { span, .. } => {
+                panic!("{}: strings can only be printed", span)
+            },
+            Expr::Identifier { name, span } => {
+                let slot = self.local_slot(name, span);
+                self.asm_mov("rbx", &slot);
+            },
+            Expr::Call { name, arguments, span } => self.emit_code_for_call(name, arguments, span),
+            Expr::Prefix { operator: TokenType::Bang, right, .. } => {
+                self.emit_code_for_negation(right); // already leaves val in rbx
+            },
+            Expr::Prefix { .. } if integer_literal(right_side).is_some() => {
+                self.asm_mov("rbx", &integer_literal(right_side).unwrap().to_string());
+            },
+            Expr::Prefix { operator: TokenType::Minus, right, .. } => {
+                self.emit_code_for_value(right);
+                self.asm_write("neg rbx");
             },
-            ExpressionType::Infix => {
-                self.emit_code_for_addition(right_side, true);
+            Expr::Prefix { operator: TokenType::Tilde, right, .. } if self.is_float(right) => {
+                panic!("{}: '~' is not supported on floats", right_side.span());
             },
-            ExpressionType::String => todo!(),
-            ExpressionType::Identifier => todo!(),
-            ExpressionType::Prefix => {
-                self.emit_code_for_negation(right_side); // already leaves val in rbx
+            Expr::Prefix { operator: TokenType::Tilde, right, .. } => {
+                self.emit_code_for_value(right);
+                self.asm_write("not rbx");
             },
-            ExpressionType::Return => {
-                panic!("can not return a return");
+            Expr::Prefix { operator, span, .. } => {
+                panic!("{}: '{}' is not a prefix operator", span, operator.operator_text())
             },
         }
-        self.asm_mov("rax", "1");
-        self.asm_write("int 0x80");
     }
 }
 
-pub fn generate_code(expressions: Vec<BoxExpression>) -> String {
-    let mut compiler = Compiler::new(expressions);
+pub fn generate_code(statements: Vec<Stmt>) -> String {
+    let mut compiler = Compiler::new(statements);
     compiler.compile();
     compiler.code
 }
@@ -3,10 +3,9 @@ use codegen::generate_code;
 use lexer::lex_program;
 use parser::parse;
 
-/*
 #[test]
 fn return_a_literal() {
-    let tokens = lex_program("return 13;");
+    let tokens = lex_program("return 13;").unwrap();
     let expressions = parse(tokens);
     let code = generate_code(expressions);
 
@@ -22,9 +21,27 @@ int 0x80
     );
 }
 
+#[test]
+fn return_a_prefixed_literal() {
+    let tokens = lex_program("return 0x1_F;").unwrap();
+    let expressions = parse(tokens);
+    let code = generate_code(expressions);
+
+    assert_eq!(
+        r#"section .text
+global _start
+_start:
+mov rbx, 31
+mov rax, 1
+int 0x80
+"#,
+        code
+    );
+}
+
 #[test]
 fn return_a_negated_positive_number() {
-    let tokens = lex_program("return !5;");
+    let tokens = lex_program("return !5;").unwrap();
     let expressions = parse(tokens);
     let code = generate_code(expressions);
 
@@ -42,7 +59,7 @@ int 0x80
 
 #[test]
 fn return_a_negated_zero() {
-    let tokens = lex_program("return !0;");
+    let tokens = lex_program("return !0;").unwrap();
     let expressions = parse(tokens);
     let code = generate_code(expressions);
 
@@ -60,7 +77,62 @@ int 0x80
 
 #[test]
 fn return_negation_of_a_negation_of_a_literal() {
-    let tokens = lex_program("return !!5;");
+    let tokens = lex_program("return !!5;").unwrap();
+    let expressions = parse(tokens);
+    let code = generate_code(expressions);
+
+    assert_eq!(
+        r#"section .text
+global _start
+_start:
+mov rbx, 0
+xor rbx, 1
+mov rax, 1
+int 0x80
+"#,
+        code
+    );
+}
+
+#[test]
+fn return_a_boolean() {
+    let tokens = lex_program("return true;").unwrap();
+    let expressions = parse(tokens);
+    let code = generate_code(expressions);
+
+    assert_eq!(
+        r#"section .text
+global _start
+_start:
+mov rbx, 1
+mov rax, 1
+int 0x80
+"#,
+        code
+    );
+}
+
+#[test]
+fn return_a_negated_boolean() {
+    let tokens = lex_program("return !false;").unwrap();
+    let expressions = parse(tokens);
+    let code = generate_code(expressions);
+
+    assert_eq!(
+        r#"section .text
+global _start
+_start:
+mov rbx, 1
+mov rax, 1
+int 0x80
+"#,
+        code
+    );
+}
+
+#[test]
+fn return_a_double_negated_boolean() {
+    let tokens = lex_program("return !!true;").unwrap();
     let expressions = parse(tokens);
     let code = generate_code(expressions);
 
@@ -77,9 +149,27 @@ int 0x80
     );
 }
 
+#[test]
+fn return_a_char() {
+    let tokens = lex_program(r"return '\n';").unwrap();
+    let expressions = parse(tokens);
+    let code = generate_code(expressions);
+
+    assert_eq!(
+        r#"section .text
+global _start
+_start:
+mov rbx, 10
+mov rax, 1
+int 0x80
+"#,
+        code
+    );
+}
+
 #[test]
 fn return_addition_of_two_numbers() {
-    let tokens = lex_program("return 12 + 4;");
+    let tokens = lex_program("return 12 + 4;").unwrap();
     let expressions = parse(tokens);
     let code = generate_code(expressions);
 
@@ -98,7 +188,7 @@ int 0x80
 
 #[test]
 fn return_addition_of_three_numbers() {
-    let tokens = lex_program("return 12 + 4 + 6;");
+    let tokens = lex_program("return 12 + 4 + 6;").unwrap();
     let expressions = parse(tokens);
     let code = generate_code(expressions);
 
@@ -118,7 +208,7 @@ int 0x80
 
 #[test]
 fn return_addition_of_four_numbers() {
-    let tokens = lex_program("return 12 + 4 + 6 + 3;");
+    let tokens = lex_program("return 12 + 4 + 6 + 3;").unwrap();
     let expressions = parse(tokens);
     let code = generate_code(expressions);
 
@@ -137,4 +227,1342 @@ int 0x80
     );
 }
 
- */
\ No newline at end of file
+#[test]
+fn multiplication_binds_tighter_than_addition() {
+    let tokens = lex_program("return 2 * 3 + 4;").unwrap();
+    let code = generate_code(parse(tokens));
+
+    assert_eq!(
+        r#"section .text
+global _start
+_start:
+mov rbx, 2
+imul rbx, 3
+add rbx, 4
+mov rax, 1
+int 0x80
+"#,
+        code
+    );
+}
+
+#[test]
+fn right_operands_that_are_not_literals_go_through_the_stack() {
+    let tokens = lex_program("return 2 + 3 * 4;").unwrap();
+    let code = generate_code(parse(tokens));
+
+    assert_eq!(
+        r#"section .text
+global _start
+_start:
+mov rbx, 2
+push rbx
+mov rbx, 3
+imul rbx, 4
+mov rcx, rbx
+pop rbx
+add rbx, rcx
+mov rax, 1
+int 0x80
+"#,
+        code
+    );
+}
+
+#[test]
+fn subtraction_is_left_associative() {
+    let tokens = lex_program("return 10 - 2 - 4 - 1;").unwrap();
+    let code = generate_code(parse(tokens));
+
+    assert_eq!(
+        r#"section .text
+global _start
+_start:
+mov rbx, 10
+sub rbx, 2
+sub rbx, 4
+sub rbx, 1
+mov rax, 1
+int 0x80
+"#,
+        code
+    );
+}
+
+#[test]
+fn division_and_remainder() {
+    let tokens = lex_program("return 100 / 7 % 4;").unwrap();
+    let code = generate_code(parse(tokens));
+
+    assert_eq!(
+        r#"section .text
+global _start
+_start:
+mov rbx, 100
+mov rcx, 7
+mov rax, rbx
+cqo
+idiv rcx
+mov rbx, rax
+mov rcx, 4
+mov rax, rbx
+cqo
+idiv rcx
+mov rbx, rdx
+mov rax, 1
+int 0x80
+"#,
+        code
+    );
+}
+
+#[test]
+fn negation_binds_tighter_than_infix_operators() {
+    let tokens = lex_program("return !0 + 1 << 2;").unwrap();
+    let code = generate_code(parse(tokens));
+
+    assert_eq!(
+        r#"section .text
+global _start
+_start:
+mov rbx, 1
+add rbx, 1
+sal rbx, 2
+mov rax, 1
+int 0x80
+"#,
+        code
+    );
+}
+
+#[test]
+fn groupings_are_computed_first() {
+    let tokens = lex_program("return (10 - 2) * ((3 + 4) - 1);").unwrap();
+    let code = generate_code(parse(tokens));
+
+    assert_eq!(
+        r#"section .text
+global _start
+_start:
+mov rbx, 10
+sub rbx, 2
+push rbx
+mov rbx, 3
+add rbx, 4
+sub rbx, 1
+mov rcx, rbx
+pop rbx
+imul rbx, rcx
+mov rax, 1
+int 0x80
+"#,
+        code
+    );
+}
+
+#[test]
+fn grouped_literals_are_still_immediates() {
+    let tokens = lex_program("return !(0) + ((2));").unwrap();
+    let code = generate_code(parse(tokens));
+
+    assert_eq!(
+        r#"section .text
+global _start
+_start:
+mov rbx, 1
+add rbx, 2
+mov rax, 1
+int 0x80
+"#,
+        code
+    );
+}
+
+#[test]
+fn comparison_gives_zero_or_one() {
+    let tokens = lex_program("return 3 < 5;").unwrap();
+    let code = generate_code(parse(tokens));
+
+    assert_eq!(
+        r#"section .text
+global _start
+_start:
+mov rbx, 3
+cmp rbx, 5
+setl al
+movzx rbx, al
+mov rax, 1
+int 0x80
+"#,
+        code
+    );
+}
+
+#[test]
+fn comparisons_are_signed() {
+    let cases = [("==", "sete"), ("!=", "setne"), ("<", "setl"), (">", "setg"), ("<=", "setle"), (">=", "setge")];
+    for (operator, instruction) in cases.iter() {
+        let tokens = lex_program(&format!("return 1 {} 2;", operator)).unwrap();
+        let code = generate_code(parse(tokens));
+        assert!(code.contains(&format!("cmp rbx, 2\n{} al\nmovzx rbx, al\n", instruction)), "{}", operator);
+    }
+}
+
+#[test]
+fn comparison_operands_are_computed_first() {
+    let tokens = lex_program("return 2 * 3 > 4 + 1 == 1;").unwrap();
+    let code = generate_code(parse(tokens));
+
+    assert_eq!(
+        r#"section .text
+global _start
+_start:
+mov rbx, 2
+imul rbx, 3
+push rbx
+mov rbx, 4
+add rbx, 1
+mov rcx, rbx
+pop rbx
+cmp rbx, rcx
+setg al
+movzx rbx, al
+cmp rbx, 1
+sete al
+movzx rbx, al
+mov rax, 1
+int 0x80
+"#,
+        code
+    );
+}
+
+#[test]
+fn logical_operators_short_circuit() {
+    let tokens = lex_program("let a = 2; return a && 0 || a;").unwrap();
+    let code = generate_code(parse(tokens));
+
+    assert_eq!(
+        r#"section .text
+global _start
+_start:
+push rbp
+mov rbp, rsp
+sub rsp, 8
+mov rbx, 2
+mov [rbp - 8], rbx
+mov rbx, [rbp - 8]
+cmp rbx, 0
+sete al
+movzx rbx, al
+xor rbx, 1
+test rbx, rbx
+jz and_end_1
+mov rbx, 1
+xor rbx, 1
+and_end_1:
+cmp rbx, 0
+sete al
+movzx rbx, al
+xor rbx, 1
+test rbx, rbx
+jnz or_end_0
+mov rbx, [rbp - 8]
+cmp rbx, 0
+sete al
+movzx rbx, al
+xor rbx, 1
+or_end_0:
+mov rax, 1
+int 0x80
+"#,
+        code
+    );
+}
+
+#[test]
+fn logical_operators_give_booleans() {
+    let code = generate_code(parse(lex_program("return -0.5 && 1;").unwrap()));
+    assert!(code.contains("ucomisd xmm0, xmm1\nsete al\nsetnp cl\nand al, cl\nmovzx rbx, al\nxor rbx, 1\ntest rbx, rbx\njz and_end_0\n"));
+
+    let code = generate_code(parse(lex_program(r#"print("{1 || 0}");"#).unwrap()));
+    assert!(code.contains("or_end_0:\ncall print_bool\n"));
+}
+
+#[test]
+fn negative_constants_are_folded() {
+    let tokens = lex_program("return 3 - -2 * ~4;").unwrap();
+    let code = generate_code(parse(tokens));
+
+    assert_eq!(
+        r#"section .text
+global _start
+_start:
+mov rbx, 3
+push rbx
+mov rbx, -2
+imul rbx, -5
+mov rcx, rbx
+pop rbx
+sub rbx, rcx
+mov rax, 1
+int 0x80
+"#,
+        code
+    );
+}
+
+#[test]
+fn smallest_integer_literal_is_loaded_whole() {
+    let tokens = lex_program("return -9223372036854775808;").unwrap();
+    let code = generate_code(parse(tokens));
+
+    assert!(code.contains("_start:\nmov rbx, -9223372036854775808\nmov rax, 1\n"));
+}
+
+#[test]
+fn negate_and_complement_expressions() {
+    let tokens = lex_program("return -(2 + 3) + ~(1 << 2);").unwrap();
+    let code = generate_code(parse(tokens));
+
+    assert_eq!(
+        r#"section .text
+global _start
+_start:
+mov rbx, 2
+add rbx, 3
+neg rbx
+push rbx
+mov rbx, 1
+sal rbx, 2
+not rbx
+mov rcx, rbx
+pop rbx
+add rbx, rcx
+mov rax, 1
+int 0x80
+"#,
+        code
+    );
+}
+
+#[test]
+fn variables_live_in_stack_slots() {
+    let tokens = lex_program("let x = 5; let y = x * 2; x = x + y; return x;").unwrap();
+    let code = generate_code(parse(tokens));
+
+    assert_eq!(
+        r#"section .text
+global _start
+_start:
+push rbp
+mov rbp, rsp
+sub rsp, 24
+mov rbx, 5
+mov [rbp - 8], rbx
+mov rbx, [rbp - 8]
+imul rbx, 2
+mov [rbp - 16], rbx
+mov rbx, [rbp - 8]
+push rbx
+mov rbx, [rbp - 16]
+mov rcx, rbx
+pop rbx
+add rbx, rcx
+mov [rbp - 8], rbx
+mov rbx, [rbp - 8]
+mov rax, 1
+int 0x80
+"#,
+        code
+    );
+}
+
+#[test]
+fn shadowing_gets_a_new_slot() {
+    let tokens = lex_program("let x = 1; let x = x + 1; return x;").unwrap();
+    let code = generate_code(parse(tokens));
+
+    assert!(code.contains(
+        "sub rsp, 24\nmov rbx, 1\nmov [rbp - 8], rbx\nmov rbx, [rbp - 8]\nadd rbx, 1\nmov [rbp - 16], rbx\nmov rbx, [rbp - 16]\n"
+    ));
+}
+
+#[test]
+fn assignment_declares_unknown_variables() {
+    let tokens = lex_program("y = 4; y = y * y; return y;").unwrap();
+    let code = generate_code(parse(tokens));
+
+    assert_eq!(
+        r#"section .text
+global _start
+_start:
+push rbp
+mov rbp, rsp
+sub rsp, 8
+mov rbx, 4
+mov [rbp - 8], rbx
+mov rbx, [rbp - 8]
+push rbx
+mov rbx, [rbp - 8]
+mov rcx, rbx
+pop rbx
+imul rbx, rcx
+mov [rbp - 8], rbx
+mov rbx, [rbp - 8]
+mov rax, 1
+int 0x80
+"#,
+        code
+    );
+}
+
+#[test]
+#[should_panic(expected = "1:16: unknown variable 'y'")]
+fn unknown_variable() {
+    generate_code(parse(lex_program("let x = 1; x + y;").unwrap()));
+}
+
+#[test]
+fn functions_are_routines() {
+    let tokens = lex_program("fn square(x) { return x * x; }").unwrap();
+    let code = generate_code(parse(tokens));
+
+    assert_eq!(
+        r#"section .text
+global _start
+_start:
+mov rbx, 0
+mov rax, 1
+int 0x80
+fn_square:
+push rbx
+push rbp
+mov rbp, rsp
+sub rsp, 8
+mov [rbp - 8], rdi
+mov rbx, [rbp - 8]
+push rbx
+mov rbx, [rbp - 8]
+mov rcx, rbx
+pop rbx
+imul rbx, rcx
+mov rax, rbx
+mov rsp, rbp
+pop rbp
+pop rbx
+ret
+"#,
+        code
+    );
+}
+
+#[test]
+fn program_runs_main() {
+    let tokens = lex_program("main() { let y = 3; } let x = 1;").unwrap();
+    let code = generate_code(parse(tokens));
+
+    assert_eq!(
+        r#"section .text
+global _start
+_start:
+push rbp
+mov rbp, rsp
+sub rsp, 8
+mov rbx, 1
+mov [rbp - 8], rbx
+call fn_main
+mov rbx, rax
+mov rax, 1
+int 0x80
+fn_main:
+push rbx
+push rbp
+mov rbp, rsp
+sub rsp, 8
+mov rbx, 3
+mov [rbp - 8], rbx
+mov rbx, 0
+mov rax, rbx
+mov rsp, rbp
+pop rbp
+pop rbx
+ret
+"#,
+        code
+    );
+}
+
+#[test]
+fn parameters_past_the_sixth_are_on_the_stack() {
+    let tokens = lex_program("fn f(a, b, c, d, e, g, h, i) { return i; }").unwrap();
+    let code = generate_code(parse(tokens));
+
+    assert!(code.contains(
+        r#"fn_f:
+push rbx
+push rbp
+mov rbp, rsp
+sub rsp, 72
+mov [rbp - 8], rdi
+mov [rbp - 16], rsi
+mov [rbp - 24], rdx
+mov [rbp - 32], rcx
+mov [rbp - 40], r8
+mov [rbp - 48], r9
+mov rax, [rbp + 24]
+mov [rbp - 56], rax
+mov rax, [rbp + 32]
+mov [rbp - 64], rax
+mov rbx, [rbp - 64]
+"#
+    ));
+}
+
+#[test]
+#[should_panic(expected = "1:28: unknown variable 'x'")]
+fn functions_do_not_see_outer_variables() {
+    generate_code(parse(lex_program("let x = 1; fn f() { return x; }").unwrap()));
+}
+
+#[test]
+#[should_panic(expected = "1:11: function 'f' is already declared")]
+fn duplicate_functions() {
+    generate_code(parse(lex_program("fn f() {} fn f() {}").unwrap()));
+}
+
+#[test]
+#[should_panic(expected = "1:10: functions can only be declared at the top level")]
+fn nested_functions() {
+    generate_code(parse(lex_program("fn f() { fn g() {} }").unwrap()));
+}
+
+#[test]
+fn calls_pass_arguments_in_registers() {
+    let tokens = lex_program("fn sub(a, b) { return a - b; } return sub(10, 2 * 3);").unwrap();
+    let code = generate_code(parse(tokens));
+
+    assert_eq!(
+        r#"section .text
+global _start
+_start:
+sub rsp, 16
+mov rbx, 10
+mov [rsp], rbx
+mov rbx, 2
+imul rbx, 3
+mov [rsp + 8], rbx
+pop rdi
+pop rsi
+call fn_sub
+mov rbx, rax
+mov rax, 1
+int 0x80
+fn_sub:
+push rbx
+push rbp
+mov rbp, rsp
+sub rsp, 24
+mov [rbp - 8], rdi
+mov [rbp - 16], rsi
+mov rbx, [rbp - 8]
+push rbx
+mov rbx, [rbp - 16]
+mov rcx, rbx
+pop rbx
+sub rbx, rcx
+mov rax, rbx
+mov rsp, rbp
+pop rbp
+pop rbx
+ret
+"#,
+        code
+    );
+}
+
+#[test]
+fn arguments_past_the_sixth_go_on_the_stack() {
+    let tokens = lex_program("fn f(a, b, c, d, e, g, h, i) { return i; } f(1, 2, 3, 4, 5, 6, 7, 8);").unwrap();
+    let code = generate_code(parse(tokens));
+
+    assert!(code.starts_with(
+        r#"section .text
+global _start
+_start:
+sub rsp, 64
+mov rbx, 1
+mov [rsp], rbx
+mov rbx, 2
+mov [rsp + 8], rbx
+mov rbx, 3
+mov [rsp + 16], rbx
+mov rbx, 4
+mov [rsp + 24], rbx
+mov rbx, 5
+mov [rsp + 32], rbx
+mov rbx, 6
+mov [rsp + 40], rbx
+mov rbx, 7
+mov [rsp + 48], rbx
+mov rbx, 8
+mov [rsp + 56], rbx
+pop rdi
+pop rsi
+pop rdx
+pop rcx
+pop r8
+pop r9
+call fn_f
+add rsp, 16
+mov rbx, rax
+mov rbx, 0
+"#
+    ));
+}
+
+#[test]
+fn call_results_are_operands() {
+    let tokens = lex_program("fn one() { return 1; } return 2 * one();").unwrap();
+    let code = generate_code(parse(tokens));
+
+    assert!(code.starts_with(
+        r#"section .text
+global _start
+_start:
+mov rbx, 2
+push rbx
+sub rsp, 8
+call fn_one
+add rsp, 8
+mov rbx, rax
+mov rcx, rbx
+pop rbx
+imul rbx, rcx
+"#
+    ));
+}
+
+#[test]
+fn function_labels_are_ascii() {
+    let tokens = lex_program("fn größe() { return 1; } fn gr_u00f6_u00dfe() { return 2; } größe();").unwrap();
+    let code = generate_code(parse(tokens));
+
+    assert!(code.contains("call fn_gr_u00f6_u00dfe\n"));
+    assert!(code.contains("fn_gr_u00f6_u00dfe:\n"));
+    assert!(code.contains("fn_gr__u00f6__u00dfe:\n"));
+    assert!(code.is_ascii());
+}
+
+#[test]
+#[should_panic(expected = "1:8: unknown function 'square'")]
+fn unknown_function() {
+    generate_code(parse(lex_program("return square(2);").unwrap()));
+}
+
+#[test]
+#[should_panic(expected = "1:22: 'f' takes 2 arguments, found 1")]
+fn wrong_number_of_arguments() {
+    generate_code(parse(lex_program("fn f(a, b) {} return f(1);").unwrap()));
+}
+
+#[test]
+fn return_a_float_truncates_it() {
+    let tokens = lex_program("return 2.75;").unwrap();
+    let code = generate_code(parse(tokens));
+
+    assert_eq!(
+        r#"section .text
+global _start
+_start:
+mov rax, 0x4006000000000000
+movq xmm0, rax
+cvttsd2si rbx, xmm0
+mov rax, 1
+int 0x80
+"#,
+        code
+    );
+}
+
+#[test]
+fn float_arithmetic_promotes_integers() {
+    let tokens = lex_program("return (1.5 + 2) * 4.0;").unwrap();
+    let code = generate_code(parse(tokens));
+
+    assert_eq!(
+        r#"section .text
+global _start
+_start:
+mov rax, 0x3ff8000000000000
+movq xmm0, rax
+sub rsp, 8
+movsd [rsp], xmm0
+mov rax, 2
+cvtsi2sd xmm0, rax
+movsd xmm1, xmm0
+movsd xmm0, [rsp]
+add rsp, 8
+addsd xmm0, xmm1
+sub rsp, 8
+movsd [rsp], xmm0
+mov rax, 0x4010000000000000
+movq xmm0, rax
+movsd xmm1, xmm0
+movsd xmm0, [rsp]
+add rsp, 8
+mulsd xmm0, xmm1
+cvttsd2si rbx, xmm0
+mov rax, 1
+int 0x80
+"#,
+        code
+    );
+}
+
+#[test]
+fn integer_operands_of_float_arithmetic_stay_integers() {
+    let tokens = lex_program("return 7 / 2 + 0.5;").unwrap();
+    let code = generate_code(parse(tokens));
+
+    assert_eq!(
+        r#"section .text
+global _start
+_start:
+mov rbx, 7
+mov rcx, 2
+mov rax, rbx
+cqo
+idiv rcx
+mov rbx, rax
+cvtsi2sd xmm0, rbx
+sub rsp, 8
+movsd [rsp], xmm0
+mov rax, 0x3fe0000000000000
+movq xmm0, rax
+movsd xmm1, xmm0
+movsd xmm0, [rsp]
+add rsp, 8
+addsd xmm0, xmm1
+cvttsd2si rbx, xmm0
+mov rax, 1
+int 0x80
+"#,
+        code
+    );
+}
+
+#[test]
+fn integer_only_operators_inside_float_arithmetic() {
+    let code = generate_code(parse(lex_program("return 5 % 2 + 0.5;").unwrap()));
+    assert!(code.contains("idiv rcx\nmov rbx, rdx\ncvtsi2sd xmm0, rbx\n"));
+
+    let code = generate_code(parse(lex_program("return (6 & 3) * 1.5;").unwrap()));
+    assert!(code.contains("and rbx, 3\ncvtsi2sd xmm0, rbx\n"));
+
+    let code = generate_code(parse(lex_program("return (1 < 2) + 0.5;").unwrap()));
+    assert!(code.contains("cmp rbx, 2\nsetl al\nmovzx rbx, al\ncvtsi2sd xmm0, rbx\n"));
+}
+
+#[test]
+fn float_variables_keep_their_fraction() {
+    let tokens = lex_program("let x = 2.5; x = x + 1; return x * 2;").unwrap();
+    let code = generate_code(parse(tokens));
+
+    assert_eq!(
+        r#"section .text
+global _start
+_start:
+push rbp
+mov rbp, rsp
+sub rsp, 8
+mov rax, 0x4004000000000000
+movq xmm0, rax
+movsd [rbp - 8], xmm0
+movsd xmm0, [rbp - 8]
+sub rsp, 8
+movsd [rsp], xmm0
+mov rax, 1
+cvtsi2sd xmm0, rax
+movsd xmm1, xmm0
+movsd xmm0, [rsp]
+add rsp, 8
+addsd xmm0, xmm1
+movsd [rbp - 8], xmm0
+movsd xmm0, [rbp - 8]
+sub rsp, 8
+movsd [rsp], xmm0
+mov rax, 2
+cvtsi2sd xmm0, rax
+movsd xmm1, xmm0
+movsd xmm0, [rsp]
+add rsp, 8
+mulsd xmm0, xmm1
+cvttsd2si rbx, xmm0
+mov rax, 1
+int 0x80
+"#,
+        code
+    );
+}
+
+#[test]
+fn integers_assigned_to_float_variables_are_converted() {
+    let code = generate_code(parse(lex_program("let x = 0.5; x = 3; return !x;").unwrap()));
+    assert!(code.contains("mov rax, 3\ncvtsi2sd xmm0, rax\nmovsd [rbp - 8], xmm0\nmovsd xmm0, [rbp - 8]\nxorpd xmm1, xmm1\n"));
+}
+
+#[test]
+#[should_panic(expected = "1:12: cannot assign a float to the integer variable 'x'")]
+fn assign_a_float_to_an_integer_variable() {
+    generate_code(parse(lex_program("let x = 1; x = 2.5;").unwrap()));
+}
+
+#[test]
+#[should_panic(expected = "1:32: functions take integers, found a float")]
+fn pass_a_float_to_a_function() {
+    generate_code(parse(lex_program("fn f(a) { return a; } return f(2.5);").unwrap()));
+}
+
+#[test]
+#[should_panic(expected = "1:30: functions return integers, found a float")]
+fn return_a_float_from_a_function() {
+    generate_code(parse(lex_program("fn f() { let b = 0.5; return b * 4; }").unwrap()));
+}
+
+#[test]
+fn negating_a_float_flips_its_sign_bit() {
+    let tokens = lex_program("return -(1.5 * 2) + -1;").unwrap();
+    let code = generate_code(parse(tokens));
+
+    assert_eq!(
+        r#"section .text
+global _start
+_start:
+mov rax, 0x3ff8000000000000
+movq xmm0, rax
+sub rsp, 8
+movsd [rsp], xmm0
+mov rax, 2
+cvtsi2sd xmm0, rax
+movsd xmm1, xmm0
+movsd xmm0, [rsp]
+add rsp, 8
+mulsd xmm0, xmm1
+mov rax, 0x8000000000000000
+movq xmm1, rax
+xorpd xmm0, xmm1
+sub rsp, 8
+movsd [rsp], xmm0
+mov rbx, -1
+cvtsi2sd xmm0, rbx
+movsd xmm1, xmm0
+movsd xmm0, [rsp]
+add rsp, 8
+addsd xmm0, xmm1
+cvttsd2si rbx, xmm0
+mov rax, 1
+int 0x80
+"#,
+        code
+    );
+}
+
+#[test]
+#[should_panic(expected = "1:8: '~' is not supported on floats")]
+fn complementing_a_float() {
+    generate_code(parse(lex_program("return ~2.5;").unwrap()));
+}
+
+#[test]
+#[should_panic(expected = "1:8: '%' is not supported on floats")]
+fn remainder_of_a_float() {
+    generate_code(parse(lex_program("return 5.5 % 2;").unwrap()));
+}
+
+#[test]
+#[should_panic(expected = "1:13: '<<' is not supported on floats")]
+fn shifting_a_float() {
+    generate_code(parse(lex_program("return 1 + (2.5 << 1);").unwrap()));
+}
+
+#[test]
+fn float_comparison_handles_nan() {
+    // NaN is unordered, which sets PF: it is unequal to everything, itself included.
+    let code = generate_code(parse(lex_program("return 0.0 / 0.0 == 0.0 / 0.0;").unwrap()));
+    assert!(code.contains("divsd xmm0, xmm1\nmovsd xmm1, xmm0\nmovsd xmm0, [rsp]\nadd rsp, 8\nucomisd xmm0, xmm1\nsete al\nsetnp cl\nand al, cl\nmovzx rbx, al\n"));
+
+    let code = generate_code(parse(lex_program("return 0.0 / 0.0 != 0.0 / 0.0;").unwrap()));
+    assert!(code.contains("ucomisd xmm0, xmm1\nsetne al\nsetp cl\nor al, cl\nmovzx rbx, al\n"));
+
+    let code = generate_code(parse(lex_program("return 1 <= 0.0 / 0.0;").unwrap()));
+    assert!(code.contains("ucomisd xmm1, xmm0\nsetae al\nmovzx rbx, al\n"));
+}
+
+#[test]
+fn float_comparisons() {
+    let code = generate_code(parse(lex_program("return 0.1 + 0.2 > 0.3;").unwrap()));
+
+    assert_eq!(
+        r#"section .text
+global _start
+_start:
+mov rax, 0x3fb999999999999a
+movq xmm0, rax
+sub rsp, 8
+movsd [rsp], xmm0
+mov rax, 0x3fc999999999999a
+movq xmm0, rax
+movsd xmm1, xmm0
+movsd xmm0, [rsp]
+add rsp, 8
+addsd xmm0, xmm1
+sub rsp, 8
+movsd [rsp], xmm0
+mov rax, 0x3fd3333333333333
+movq xmm0, rax
+movsd xmm1, xmm0
+movsd xmm0, [rsp]
+add rsp, 8
+ucomisd xmm0, xmm1
+seta al
+movzx rbx, al
+mov rax, 1
+int 0x80
+"#,
+        code
+    );
+}
+
+#[test]
+fn print_writes_literals_as_one_string() {
+    let tokens = lex_program(r#"print("sum {5} {true} {'x'} {2.5}"); print("sum 5 true x 2.5");"#).unwrap();
+    let code = generate_code(parse(tokens));
+
+    assert_eq!(
+        r#"section .text
+global _start
+_start:
+mov rax, 1
+mov rdi, 1
+lea rsi, [rel str_0]
+mov rdx, 17
+syscall
+mov rax, 1
+mov rdi, 1
+lea rsi, [rel str_0]
+mov rdx, 17
+syscall
+mov rbx, 0
+mov rax, 1
+int 0x80
+section .data
+str_0: db 115, 117, 109, 32, 53, 32, 116, 114, 117, 101, 32, 120, 32, 50, 46, 53, 10
+"#,
+        code
+    );
+}
+
+#[test]
+fn print_a_computed_boolean() {
+    let tokens = lex_program(r#"print("{!0}"); return 0;"#).unwrap();
+    let code = generate_code(parse(tokens));
+
+    assert_eq!(
+        r#"section .text
+global _start
+_start:
+mov rbx, 1
+call print_bool
+mov rax, 1
+mov rdi, 1
+lea rsi, [rel str_0]
+mov rdx, 1
+syscall
+mov rbx, 0
+mov rax, 1
+int 0x80
+print_bool:
+lea rsi, [rel str_1]
+mov rdx, 4
+test rbx, rbx
+jnz print_bool_write
+lea rsi, [rel str_2]
+mov rdx, 5
+print_bool_write:
+mov rax, 1
+mov rdi, 1
+syscall
+ret
+section .data
+str_0: db 10
+str_1: db 116, 114, 117, 101
+str_2: db 102, 97, 108, 115, 101
+"#,
+        code
+    );
+}
+
+#[test]
+fn print_a_comparison() {
+    let tokens = lex_program(r#"print("{1 + 1 == 2}");"#).unwrap();
+    let code = generate_code(parse(tokens));
+
+    assert!(code.starts_with(
+        r#"section .text
+global _start
+_start:
+mov rbx, 1
+add rbx, 1
+cmp rbx, 2
+sete al
+movzx rbx, al
+call print_bool
+"#
+    ));
+}
+
+#[test]
+fn print_a_computed_integer() {
+    let tokens = lex_program("print(40 + 2);").unwrap();
+    let code = generate_code(parse(tokens));
+
+    assert_eq!(
+        r#"section .text
+global _start
+_start:
+mov rbx, 40
+add rbx, 2
+call print_int
+mov rax, 1
+mov rdi, 1
+lea rsi, [rel str_0]
+mov rdx, 1
+syscall
+mov rbx, 0
+mov rax, 1
+int 0x80
+print_int:
+mov rax, rbx
+lea rsi, [rsp - 1]
+mov rcx, 0
+mov r8, 10
+test rax, rax
+jns print_int_digit
+neg rax
+print_int_digit:
+mov rdx, 0
+div r8
+add dl, 48
+mov [rsi], dl
+dec rsi
+inc rcx
+test rax, rax
+jnz print_int_digit
+test rbx, rbx
+jns print_int_write
+mov byte [rsi], 45
+dec rsi
+inc rcx
+print_int_write:
+inc rsi
+mov rdx, rcx
+mov rax, 1
+mov rdi, 1
+syscall
+ret
+section .data
+str_0: db 10
+"#,
+        code
+    );
+}
+
+#[test]
+#[should_panic(expected = "1:9: strings can only be printed")]
+fn interpolated_string_as_a_value() {
+    generate_code(parse(lex_program(r#"let s = "a{1}";"#).unwrap()));
+}
+
+#[test]
+#[should_panic(expected = "1:8: strings can only be printed")]
+fn return_a_string() {
+    generate_code(parse(lex_program(r#"return "abc";"#).unwrap()));
+}
+
+#[test]
+#[should_panic(expected = "1:9: strings can only be printed")]
+fn negate_a_string() {
+    generate_code(parse(lex_program(r#"return !"abc";"#).unwrap()));
+}
+
+#[test]
+fn while_loop() {
+    let tokens = lex_program("let i = 3; while i { let j = i; i = j - 1; } return i;").unwrap();
+    let code = generate_code(parse(tokens));
+
+    assert_eq!(
+        r#"section .text
+global _start
+_start:
+push rbp
+mov rbp, rsp
+sub rsp, 24
+mov rbx, 3
+mov [rbp - 8], rbx
+while_start_0:
+mov rbx, [rbp - 8]
+cmp rbx, 0
+sete al
+movzx rbx, al
+test rbx, rbx
+jnz while_end_1
+mov rbx, [rbp - 8]
+mov [rbp - 16], rbx
+mov rbx, [rbp - 16]
+sub rbx, 1
+mov [rbp - 8], rbx
+jmp while_start_0
+while_end_1:
+mov rbx, [rbp - 8]
+mov rax, 1
+int 0x80
+"#,
+        code
+    );
+}
+
+#[test]
+#[should_panic(expected = "1:50: unknown variable 'j'")]
+fn while_body_variables_go_out_of_scope() {
+    generate_code(parse(lex_program("let i = 0; while i < 2 { let j = 1; i = i + j; } j;").unwrap()));
+}
+
+#[test]
+fn complex_sample_program() {
+    let tokens = lex_program(include_str!("../programs/complex.vvdl")).unwrap();
+    let code = generate_code(parse(tokens));
+
+    assert_eq!(
+        r#"section .text
+global _start
+_start:
+call fn_main
+mov rbx, rax
+mov rax, 1
+int 0x80
+fn_square:
+push rbx
+push rbp
+mov rbp, rsp
+sub rsp, 8
+mov [rbp - 8], rdi
+mov rbx, [rbp - 8]
+push rbx
+mov rbx, [rbp - 8]
+mov rcx, rbx
+pop rbx
+imul rbx, rcx
+mov rax, rbx
+mov rsp, rbp
+pop rbp
+pop rbx
+ret
+fn_main:
+push rbx
+push rbp
+mov rbp, rsp
+sub rsp, 8
+sub rsp, 8
+mov rbx, 4
+mov [rsp], rbx
+pop rdi
+call fn_square
+mov rbx, rax
+mov [rbp - 8], rbx
+while_start_0:
+mov rbx, [rbp - 8]
+cmp rbx, 12
+setg al
+movzx rbx, al
+cmp rbx, 0
+sete al
+movzx rbx, al
+test rbx, rbx
+jnz while_end_1
+mov rax, 1
+mov rdi, 1
+lea rsi, [rel str_0]
+mov rdx, 10
+syscall
+mov rbx, [rbp - 8]
+call print_int
+mov rax, 1
+mov rdi, 1
+lea rsi, [rel str_1]
+mov rdx, 1
+syscall
+mov rbx, [rbp - 8]
+sub rbx, 1
+mov [rbp - 8], rbx
+jmp while_start_0
+while_end_1:
+mov rbx, 0
+mov rax, rbx
+mov rsp, rbp
+pop rbp
+pop rbx
+ret
+print_int:
+mov rax, rbx
+lea rsi, [rsp - 1]
+mov rcx, 0
+mov r8, 10
+test rax, rax
+jns print_int_digit
+neg rax
+print_int_digit:
+mov rdx, 0
+div r8
+add dl, 48
+mov [rsi], dl
+dec rsi
+inc rcx
+test rax, rax
+jnz print_int_digit
+test rbx, rbx
+jns print_int_write
+mov byte [rsi], 45
+dec rsi
+inc rcx
+print_int_write:
+inc rsi
+mov rdx, rcx
+mov rax, 1
+mov rdi, 1
+syscall
+ret
+section .data
+str_0: db 97, 110, 115, 119, 101, 114, 32, 105, 115, 32
+str_1: db 10
+"#,
+        code
+    );
+}
+
+#[test]
+fn print_a_computed_float() {
+    let tokens = lex_program(r#"let x = 2.5; print("{-x}");"#).unwrap();
+    let code = generate_code(parse(tokens));
+
+    assert_eq!(
+        r#"section .text
+global _start
+_start:
+push rbp
+mov rbp, rsp
+sub rsp, 8
+mov rax, 0x4004000000000000
+movq xmm0, rax
+movsd [rbp - 8], xmm0
+movsd xmm0, [rbp - 8]
+mov rax, 0x8000000000000000
+movq xmm1, rax
+xorpd xmm0, xmm1
+call print_float
+mov rax, 1
+mov rdi, 1
+lea rsi, [rel str_0]
+mov rdx, 1
+syscall
+mov rbx, 0
+mov rax, 1
+int 0x80
+print_float:
+movq rax, xmm0
+btr rax, 63
+setc r9b
+mov rcx, 0x7ff0000000000000
+cmp rax, rcx
+ja print_float_nan
+je print_float_infinity
+movq xmm0, rax
+cvttsd2si r10, xmm0
+cvtsi2sd xmm1, r10
+subsd xmm0, xmm1
+mov rcx, 0x412e848000000000
+movq xmm1, rcx
+mulsd xmm0, xmm1
+cvtsd2si rax, xmm0
+cmp rax, 1000000
+jne print_float_fraction
+inc r10
+mov rax, 0
+print_float_fraction:
+lea rsi, [rsp - 1]
+mov rcx, 6
+mov r8, 10
+print_float_trim:
+cmp rcx, 1
+je print_float_fraction_digit
+mov r11, rax
+mov rdx, 0
+div r8
+test rdx, rdx
+jnz print_float_trimmed
+dec rcx
+jmp print_float_trim
+print_float_trimmed:
+mov rax, r11
+print_float_fraction_digit:
+mov rdx, 0
+div r8
+add dl, 48
+mov [rsi], dl
+dec rsi
+dec rcx
+jnz print_float_fraction_digit
+mov byte [rsi], 46
+dec rsi
+mov rax, r10
+print_float_integer_digit:
+mov rdx, 0
+div r8
+add dl, 48
+mov [rsi], dl
+dec rsi
+test rax, rax
+jnz print_float_integer_digit
+test r9b, r9b
+jz print_float_digits
+mov byte [rsi], 45
+dec rsi
+print_float_digits:
+lea rdx, [rsp - 1]
+sub rdx, rsi
+inc rsi
+jmp print_float_write
+print_float_nan:
+lea rsi, [rel str_1]
+mov rdx, 3
+jmp print_float_write
+print_float_infinity:
+lea rsi, [rel str_2]
+mov rdx, 4
+test r9b, r9b
+jnz print_float_write
+inc rsi
+dec rdx
+print_float_write:
+mov rax, 1
+mov rdi, 1
+syscall
+ret
+section .data
+str_0: db 10
+str_1: db 78, 97, 78
+str_2: db 45, 105, 110, 102
+"#,
+        code
+    );
+}
@@ -1,135 +1,822 @@
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+use std::fmt;
+use std::ops::Range;
+use std::rc::Rc;
+use crate::tokens::Span;
+use crate::tokens::SharedStr;
 use crate::tokens::Token;
 use crate::tokens::TokenType;
+use crate::tokens::Trivia;
+use crate::tokens::TriviaKind;
 
-struct Lexer {
-    code: Vec<char>,
-    tokens: Vec<Token>,
-    current: usize,
-    current_char: char,
+#[derive(Debug, PartialEq, Clone)]
+pub enum LexErrorKind {
+    UnrecognizedChar(char),
+    UnterminatedString,
+    UnterminatedInterpolation,
+    UnterminatedChar,
+    InvalidCharLiteral(String),
+    UnterminatedComment,
+    InvalidEscape(String),
+    MalformedNumber(String),
+    InvalidDigit(char, u32),
+    IntegerOverflow(String),
+    FloatOverflow(String),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct LexError {
+    pub kind: LexErrorKind,
+    pub span: Span,
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.kind {
+            LexErrorKind::UnrecognizedChar(c) => write!(f, "{}: unrecognized character '{}'", self.span, c),
+            LexErrorKind::UnterminatedString => write!(f, "{}: unterminated string literal", self.span),
+            LexErrorKind::UnterminatedInterpolation => {
+                write!(f, "{}: string interpolation is missing its closing '}}'", self.span)
+            }
+            LexErrorKind::UnterminatedChar => write!(f, "{}: unterminated character literal", self.span),
+            LexErrorKind::InvalidCharLiteral(literal) => {
+                write!(f, "{}: character literal {} must contain exactly one character", self.span, literal)
+            }
+            LexErrorKind::UnterminatedComment => write!(f, "{}: unterminated block comment", self.span),
+            LexErrorKind::InvalidEscape(escape) => write!(f, "{}: invalid escape sequence '{}'", self.span, escape),
+            LexErrorKind::MalformedNumber(literal) => write!(f, "{}: malformed number '{}'", self.span, literal),
+            LexErrorKind::InvalidDigit(digit, radix) => {
+                write!(f, "{}: invalid digit '{}' in base {} literal", self.span, digit, radix)
+            }
+            LexErrorKind::IntegerOverflow(literal) => {
+                write!(f, "{}: integer literal '{}' does not fit in 64 bits", self.span, literal)
+            }
+            LexErrorKind::FloatOverflow(literal) => {
+                write!(f, "{}: float literal '{}' is too large for a 64-bit float", self.span, literal)
+            }
+        }
+    }
+}
+
+/// A string interpolation whose expression is being lexed.
+struct Interpolation {
+    /// Braces opened inside the expression and not closed yet. The '}' that
+    /// comes at depth zero resumes the string.
+    depth: usize,
+    /// Offset, line and column of the string that opened it.
+    start: (usize, usize, usize),
+}
+
+/// What a single call to `Lexer::scan` produced.
+enum Scanned {
+    Token(Token),
+    Trivia(Trivia),
+    Nothing,
+}
+
+/// Streaming lexer over a source string. Tokens are scanned on demand as the
+/// lexer is iterated, so a whole program never has to be lexed up front.
+/// Errors are yielded in place and lexing carries on after them.
+///
+/// The source is walked byte by byte, only decoding characters outside of
+/// ASCII. Token texts are never copied: they are slices of `source`, which
+/// the caller hands over already shared so the tokens can keep it alive.
+pub struct Lexer {
+    source: Rc<str>,
+    pending: VecDeque<LexError>,
+    interpolations: Vec<Interpolation>,
+    keep_trivia: bool,
+    leading_trivia: Vec<Trivia>,
+    eof_emitted: bool,
+    file: Rc<str>,
+    offset: usize,
+    line: usize,
+    column: usize,
+    /// Offset, line and column where the token being scanned starts.
+    start: (usize, usize, usize),
 }
 
 impl Lexer {
-    fn new() -> Lexer {
+    pub fn new(file: &str, source: Rc<str>) -> Lexer {
         Lexer {
-            code: Vec::new(),
-            tokens: Vec::new(),
-            current: 0,
-            current_char: '\0',
+            source,
+            pending: VecDeque::new(),
+            interpolations: Vec::new(),
+            keep_trivia: false,
+            leading_trivia: Vec::new(),
+            eof_emitted: false,
+            file: Rc::from(file),
+            offset: 0,
+            line: 1,
+            column: 1,
+            start: (0, 1, 1),
         }
     }
 
+    /// Keeps whitespace and comments as trivia attached to the tokens, and
+    /// ends the stream with an `Eof` token holding whatever trivia is left.
+    /// Concatenating every token's leading trivia, literal and trailing trivia
+    /// then gives back the exact source, see `rebuild_source`.
+    pub fn with_trivia(mut self) -> Lexer {
+        self.keep_trivia = true;
+        self
+    }
+
+    fn at_end(&self) -> bool {
+        self.offset >= self.source.len()
+    }
+
     fn peek(&self) -> char {
-        if self.current >= self.code.len() {
-            '\0'
-        } else {
-            self.code[self.current]
+        match self.source.as_bytes().get(self.offset) {
+            Some(b) if b.is_ascii() => *b as char,
+            Some(_) => self.source[self.offset..].chars().next().unwrap(),
+            None => '\0',
+        }
+    }
+
+    /// Looks `n` characters past the current one without consuming anything.
+    fn peek_nth(&self, n: usize) -> char {
+        let bytes = &self.source.as_bytes()[self.offset..];
+        if bytes.len() > n && bytes[..=n].is_ascii() {
+            return bytes[n] as char;
         }
+        self.source[self.offset..].chars().nth(n).unwrap_or('\0')
     }
 
-    fn next(&mut self) -> char {
+    /// Consumes ASCII bytes as long as `accept` holds, returning whether it
+    /// stopped at a non-ASCII character that the caller has to look at.
+    fn advance_ascii_while(&mut self, accept: impl Fn(u8) -> bool) -> bool {
+        let bytes = self.source.as_bytes();
+        let start = self.offset;
+        while let Some(&b) = bytes.get(self.offset) {
+            if !b.is_ascii() || b == b'\n' || !accept(b) {
+                break;
+            }
+            self.offset += 1;
+        }
+        self.column += self.offset - start;
+        bytes.get(self.offset).is_some_and(|b| !b.is_ascii())
+    }
+
+    /// Consumes characters up to the first byte for which `stop` holds, or
+    /// up to the end. `stop` must only hold for ASCII bytes, which never
+    /// occur inside a multi-byte character.
+    fn advance_until(&mut self, stop: impl Fn(u8) -> bool) {
+        let bytes = self.source.as_bytes();
+        while let Some(&b) = bytes.get(self.offset) {
+            if stop(b) {
+                break;
+            }
+            self.offset += 1;
+            if b == b'\n' {
+                self.line += 1;
+                self.column = 1;
+            } else if b & 0xc0 != 0x80 {
+                self.column += 1;
+            }
+        }
+    }
+
+    fn advance(&mut self) -> char {
         let nc = self.peek();
-        self.current += 1;
-        self.current_char = nc;
+        if !self.at_end() {
+            self.offset += nc.len_utf8();
+            if nc == '\n' {
+                self.line += 1;
+                self.column = 1;
+            } else {
+                self.column += 1;
+            }
+        }
         nc
     }
 
-    fn scan(&mut self, program: &str) {
-        self.code = program.chars().collect::<Vec<char>>();
-
-        while self.current < self.code.len() {
-            let c = self.next().to_string();
-
-            let (tokentype, literal): (TokenType, String) = match c.as_str() {
-                c if c.trim().is_empty() => {(TokenType::None, "".to_string())}
-                "+" => (TokenType::Plus, c),
-                "-" => (TokenType::Minus, c),
-                "*" => (TokenType::Asterisk, c),
-                ";" => (TokenType::Semicolon, c),
-                "(" => (TokenType::Lparen, c),
-                ")" => (TokenType::Rparen, c),
-                "{" => (TokenType::Lbrace, c),
-                "}" => (TokenType::Rbrace, c),
-                "=" => {
-                    if self.peek() == '=' {
-                        self.next();
-                        (TokenType::Equals, "==".to_string())
-                    } else {
-                        (TokenType::Assignment, c)
-                    }
+    /// Span from the given start position up to the current position.
+    fn span_from(&self, offset: usize, line: usize, column: usize) -> Span {
+        Span {
+            file: self.file.clone(),
+            offset,
+            len: self.offset - offset,
+            line,
+            column,
+        }
+    }
+
+    fn at_trivia(&self) -> bool {
+        self.peek().is_whitespace() || self.peek() == '/' && matches!(self.peek_nth(1), '/' | '*')
+    }
+
+    /// Scans the next token or piece of trivia. Trivia is only built when it
+    /// is kept; invalid tokens give `Nothing` and their errors go to `pending`.
+    fn scan(&mut self) -> Scanned {
+        self.start = (self.offset, self.line, self.column);
+        let c = self.advance();
+
+        let tokentype = match c {
+            c if c.is_whitespace() => {
+                self.skip_whitespace(c);
+                return self.trivia(TriviaKind::Whitespace);
+            }
+            '+' => TokenType::Plus,
+            '-' => TokenType::Minus,
+            '*' => TokenType::Asterisk,
+            '%' => TokenType::Percent,
+            '^' => TokenType::Caret,
+            '~' => TokenType::Tilde,
+            ',' => TokenType::Comma,
+            ';' => TokenType::Semicolon,
+            '(' => TokenType::Lparen,
+            ')' => TokenType::Rparen,
+            '{' => {
+                if let Some(interpolation) = self.interpolations.last_mut() {
+                    interpolation.depth += 1;
                 }
-                "!" => {
-                    if self.peek() == '=' {
-                        self.next();
-                        (TokenType::Unequal, "!=".to_string())
-                    } else {
-                        (TokenType::Bang, c)
-                    }
+                TokenType::Lbrace
+            }
+            '}' if self.interpolations.last().is_some_and(|interpolation| interpolation.depth == 0) => {
+                self.scan_string_continuation()
+            }
+            '}' => {
+                if let Some(interpolation) = self.interpolations.last_mut() {
+                    interpolation.depth -= 1;
                 }
-                "<" => {
-                    if self.peek() == '=' {
-                        self.next();
-                        (TokenType::Lteq, "<=".to_string())
-                    } else {
-                        (TokenType::Lt, c)
-                    }
+                TokenType::Rbrace
+            }
+            '/' if self.peek() == '/' => {
+                self.skip_line_comment();
+                return self.trivia(TriviaKind::LineComment);
+            }
+            '/' if self.peek() == '*' => {
+                self.skip_block_comment();
+                return self.trivia(TriviaKind::BlockComment);
+            }
+            '/' => TokenType::Slash,
+            '&' => {
+                if self.peek() == '&' {
+                    self.advance();
+                    TokenType::And
+                } else {
+                    TokenType::Ampersand
                 }
-                ">" => {
-                    if self.peek() == '=' {
-                        self.next();
-                        (TokenType::Gteq, ">=".to_string())
-                    } else {
-                        (TokenType::Gt, c)
-                    }
+            }
+            '|' => {
+                if self.peek() == '|' {
+                    self.advance();
+                    TokenType::Or
+                } else {
+                    TokenType::Pipe
+                }
+            }
+            '=' => {
+                if self.peek() == '=' {
+                    self.advance();
+                    TokenType::Equals
+                } else {
+                    TokenType::Assignment
+                }
+            }
+            '!' => {
+                if self.peek() == '=' {
+                    self.advance();
+                    TokenType::Unequal
+                } else {
+                    TokenType::Bang
+                }
+            }
+            '<' => {
+                if self.peek() == '=' {
+                    self.advance();
+                    TokenType::Lteq
+                } else if self.peek() == '<' {
+                    self.advance();
+                    TokenType::Lshift
+                } else {
+                    TokenType::Lt
                 }
-                "\"" => self.scan_string(),
-                c if c.chars().nth(0).unwrap().is_ascii_alphabetic() => self.scan_identifier(),
-                c if c.chars().nth(0).unwrap().is_ascii_digit() => self.scan_number(),
-                _ => panic!("unrecognized char: '{}'", c),
-            };
-            if tokentype != TokenType::None {
-                self.tokens.push(Token::new(tokentype, literal));
             }
+            '>' => {
+                if self.peek() == '=' {
+                    self.advance();
+                    TokenType::Gteq
+                } else if self.peek() == '>' {
+                    self.advance();
+                    TokenType::Rshift
+                } else {
+                    TokenType::Gt
+                }
+            }
+            '"' => self.scan_string(),
+            'r' if self.at_raw_string() => self.scan_raw_string(),
+            '\'' => self.scan_char(),
+            c if is_identifier_start(c) => self.scan_identifier(),
+            c if c.is_ascii_digit() => self.scan_number(),
+            c => {
+                self.error(LexErrorKind::UnrecognizedChar(c));
+                TokenType::None
+            }
+        };
+
+        if tokentype == TokenType::None {
+            return Scanned::Nothing;
+        }
+        let span = self.span_from(self.start.0, self.start.1, self.start.2);
+        Scanned::Token(Token::new(tokentype, self.shared_lexeme(), span))
+    }
+
+    fn trivia(&self, kind: TriviaKind) -> Scanned {
+        if !self.keep_trivia {
+            return Scanned::Nothing;
         }
+        let span = self.span_from(self.start.0, self.start.1, self.start.2);
+        Scanned::Trivia(Trivia { kind, text: self.shared_lexeme(), span })
     }
 
-    fn scan_generic(&mut self, ttype: TokenType) -> String {
-        let mut literal = String::from("");
-        if ttype != TokenType::String {
-            literal.push(self.current_char);
+    /// Trivia following a token on the same line, including the newline.
+    fn scan_trailing_trivia(&mut self) -> Vec<Trivia> {
+        let mut trailing = Vec::new();
+        while !self.at_end() && self.at_trivia() {
+            if let Scanned::Trivia(trivia) = self.scan() {
+                let ends_line = trivia.text.ends_with('\n');
+                trailing.push(trivia);
+                if ends_line {
+                    break;
+                }
+            }
         }
+        trailing
+    }
+
+    /// Source text of the token being scanned, sharing the source.
+    fn shared_lexeme(&self) -> SharedStr {
+        SharedStr::slice(&self.source, self.start.0..self.offset)
+    }
 
-        while self.current <= self.code.len() {
+    /// Source text of the token being scanned.
+    fn lexeme(&self) -> &str {
+        &self.source[self.start.0..self.offset]
+    }
+
+    fn error(&mut self, kind: LexErrorKind) {
+        let span = self.span_from(self.start.0, self.start.1, self.start.2);
+        self.pending.push_back(LexError { kind, span });
+    }
+
+    /// Skips a run of whitespace whose first character `c` was already
+    /// consumed. A run ends after a newline, so trailing trivia can stop there.
+    fn skip_whitespace(&mut self, c: char) {
+        if c == '\n' {
+            return;
+        }
+        while !self.at_end() {
+            self.advance_ascii_while(|b| b == b' ' || b == b'\t');
+            if !self.peek().is_whitespace() {
+                return;
+            }
+            if self.advance() == '\n' {
+                return;
+            }
+        }
+    }
+
+    fn skip_line_comment(&mut self) {
+        self.advance_until(|b| b == b'\n');
+    }
+
+    /// Skips a block comment whose leading '/' was already consumed.
+    /// Block comments nest, so every "/*" needs its own "*/".
+    fn skip_block_comment(&mut self) {
+        self.advance();
+        let mut depth = 1;
+        while depth > 0 {
+            if self.at_end() {
+                self.error(LexErrorKind::UnterminatedComment);
+                return;
+            }
+            self.advance_until(|b| b == b'/' || b == b'*');
+            let c = self.advance();
+            if c == '/' && self.peek() == '*' {
+                self.advance();
+                depth += 1;
+            } else if c == '*' && self.peek() == '/' {
+                self.advance();
+                depth -= 1;
+            }
+        }
+    }
+
+    /// Scans up to and including the closing `quote`, the opening one being
+    /// already consumed. With `interpolate`, an unescaped '{' closes it too.
+    /// Returns the closing character, or `None` if the source ends first.
+    /// Strings can span lines, but character literals end with the line.
+    fn scan_quoted(&mut self, quote: char, interpolate: bool) -> Option<char> {
+        let quote_byte = quote as u8;
+        loop {
+            self.advance_until(|b| b == quote_byte || b == b'\\' || b == b'{' || b == b'\n');
             let c = self.peek();
-            if !c.is_ascii_alphanumeric() && !(ttype == TokenType::String && c.is_whitespace()) {
-                if c == '"' && ttype == TokenType::String {
-                    self.next();
+            if self.at_end() || c == '\n' && quote == '\'' {
+                return None;
+            }
+            self.advance();
+            match c {
+                c if c == quote || c == '{' && interpolate => return Some(c),
+                '\\' if !self.at_end() && self.peek() != '\n' => {
+                    let escaped = self.advance();
+                    // The braces of a `\u{...}` escape don't start an interpolation.
+                    if escaped == 'u' && self.peek() == '{' {
+                        while !self.at_end() && !matches!(self.peek(), '}' | '\n' | '"' | '\'') {
+                            self.advance();
+                        }
+                        if self.peek() == '}' {
+                            self.advance();
+                        }
+                    }
                 }
-                break;
+                _ => {}
             }
-            literal.push(c);
-            self.next();
         }
+    }
 
-        literal
+    /// Decodes the contents between the quotes of the current lexeme,
+    /// queueing an error for every invalid escape sequence. Contents without
+    /// escapes are borrowed from the source.
+    fn unescape_lexeme(&mut self) -> Cow<'_, str> {
+        let span = self.span_from(self.start.0, self.start.1, self.start.2);
+        let literal = &self.source[self.start.0..self.offset];
+        if !literal.contains('\\') {
+            return Cow::Borrowed(&literal[1..literal.len() - 1]);
+        }
+        let pending = &mut self.pending;
+        Cow::Owned(unescape(&literal[1..literal.len() - 1], |range, kind| {
+            let span = span.within(literal, range.start + 1..range.end + 1);
+            pending.push_back(LexError { kind, span });
+        }))
     }
 
-    fn scan_string(&mut self) -> (TokenType, String) {
-        (TokenType::String, self.scan_generic(TokenType::String))
+    /// Scans a string literal whose opening quote was already consumed. The
+    /// literal keeps the raw source, quotes and escapes included; escapes are
+    /// only validated here and decoded later through `unescape`.
+    ///
+    /// An unescaped '{' ends the literal as a `StringHead`, and the tokens up
+    /// to the matching '}' are an interpolated expression. The string then
+    /// goes on as a `StringMiddle` up to the next '{', or ends as a
+    /// `StringTail` at the closing quote.
+    fn scan_string(&mut self) -> TokenType {
+        match self.scan_quoted('"', true) {
+            Some('{') => {
+                self.unescape_lexeme();
+                self.interpolations.push(Interpolation { depth: 0, start: self.start });
+                TokenType::StringHead
+            }
+            Some(_) => {
+                self.unescape_lexeme();
+                TokenType::String
+            }
+            None => {
+                self.error(LexErrorKind::UnterminatedString);
+                TokenType::None
+            }
+        }
     }
 
-    fn scan_identifier(&mut self) -> (TokenType, String) {
-        let identifier = self.scan_generic(TokenType::Identifier);
-        (Token::type_given_identifier(&identifier), identifier)
+    /// Scans the rest of an interpolated string after the '}' that closes
+    /// one of its expressions.
+    fn scan_string_continuation(&mut self) -> TokenType {
+        match self.scan_quoted('"', true) {
+            Some('{') => {
+                self.unescape_lexeme();
+                TokenType::StringMiddle
+            }
+            Some(_) => {
+                self.unescape_lexeme();
+                self.interpolations.pop();
+                TokenType::StringTail
+            }
+            None => {
+                self.error(LexErrorKind::UnterminatedString);
+                self.interpolations.pop();
+                TokenType::None
+            }
+        }
     }
 
-    fn scan_number(&mut self) -> (TokenType, String) {
-        (TokenType::Number, self.scan_generic(TokenType::Number))
+    /// Whether the 'r' just consumed starts a raw string, that is, whether it
+    /// is followed by any number of '#' and a '"'.
+    fn at_raw_string(&self) -> bool {
+        let hashes = self.source[self.offset..].chars().take_while(|c| *c == '#').count();
+        self.peek_nth(hashes) == '"'
+    }
+
+    /// Scans a raw string such as `r"C:\path"` or `r#"say "hi""#`, whose 'r'
+    /// was already consumed. Nothing inside is escaped or interpolated, and
+    /// the literal only ends at a '"' followed by as many '#' as it opened with.
+    fn scan_raw_string(&mut self) -> TokenType {
+        let mut hashes = 0;
+        while self.advance() == '#' {
+            hashes += 1;
+        }
+        loop {
+            self.advance_until(|b| b == b'"');
+            if self.at_end() {
+                self.error(LexErrorKind::UnterminatedString);
+                return TokenType::None;
+            }
+            if self.advance() == '"' && (0..hashes).all(|n| self.peek_nth(n) == '#') {
+                for _ in 0..hashes {
+                    self.advance();
+                }
+                return TokenType::RawString;
+            }
+        }
+    }
+
+    /// Scans a character literal whose opening quote was already consumed. It
+    /// takes the same escapes as strings but must decode to a single character.
+    fn scan_char(&mut self) -> TokenType {
+        if self.scan_quoted('\'', false).is_none() {
+            self.error(LexErrorKind::UnterminatedChar);
+            return TokenType::None;
+        }
+
+        let errors = self.pending.len();
+        let length = self.unescape_lexeme().chars().count();
+        if self.pending.len() > errors {
+            return TokenType::None;
+        }
+        if length != 1 {
+            self.error(LexErrorKind::InvalidCharLiteral(self.lexeme().to_string()));
+            return TokenType::None;
+        }
+        TokenType::Char
+    }
+
+    fn scan_identifier(&mut self) -> TokenType {
+        while self.advance_ascii_while(|b| b.is_ascii_alphanumeric() || b == b'_')
+            && is_identifier_continue(self.peek())
+        {
+            self.advance();
+        }
+
+        Token::type_given_identifier(self.lexeme())
+    }
+
+    /// Scans a number whose first digit was already consumed. Decimal numbers
+    /// with a fractional part or an exponent are floats, everything else is
+    /// an integer.
+    fn scan_number(&mut self) -> TokenType {
+        let prefixed = self.lexeme() == "0" && matches!(self.peek(), 'x' | 'b' | 'o');
+        let mut is_float = false;
+        if !prefixed {
+            self.skip_digits();
+            if self.peek() == '.' && self.peek_nth(1).is_ascii_digit() {
+                self.advance();
+                self.skip_digits();
+                is_float = true;
+            }
+            let exponent_digit = match self.peek_nth(1) {
+                '+' | '-' => self.peek_nth(2),
+                c => c,
+            };
+            if matches!(self.peek(), 'e' | 'E') && exponent_digit.is_ascii_digit() {
+                self.advance();
+                if matches!(self.peek(), '+' | '-') {
+                    self.advance();
+                }
+                self.skip_digits();
+                is_float = true;
+            }
+        }
+        // Anything alphanumeric glued to the number is part of it, so that
+        // `12abc` is reported as a bad number rather than `12` and `abc`.
+        self.advance_ascii_while(|b| b.is_ascii_alphanumeric() || b == b'_');
+
+        let (result, tokentype) = if is_float {
+            (parse_float(self.lexeme()).map(|_| ()), TokenType::Float)
+        } else {
+            (parse_integer_magnitude(self.lexeme()).map(|_| ()), TokenType::Number)
+        };
+        if let Err(kind) = result {
+            self.error(kind);
+            return TokenType::None;
+        }
+        tokentype
+    }
+
+    fn skip_digits(&mut self) {
+        self.advance_ascii_while(|b| b.is_ascii_digit() || b == b'_');
+    }
+}
+
+impl Iterator for Lexer {
+    type Item = Result<Token, LexError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(error) = self.pending.pop_front() {
+                return Some(Err(error));
+            }
+            if self.at_end() {
+                if let Some(interpolation) = self.interpolations.pop() {
+                    self.start = interpolation.start;
+                    self.error(LexErrorKind::UnterminatedInterpolation);
+                    continue;
+                }
+                if !self.keep_trivia || self.eof_emitted {
+                    return None;
+                }
+                self.eof_emitted = true;
+                let span = self.span_from(self.offset, self.line, self.column);
+                let mut eof = Token::new(TokenType::Eof, String::new(), span);
+                eof.leading_trivia = std::mem::take(&mut self.leading_trivia);
+                return Some(Ok(eof));
+            }
+            match self.scan() {
+                Scanned::Token(mut token) => {
+                    if self.keep_trivia {
+                        token.leading_trivia = std::mem::take(&mut self.leading_trivia);
+                        token.trailing_trivia = self.scan_trailing_trivia();
+                    }
+                    return Some(Ok(token));
+                }
+                Scanned::Trivia(trivia) => self.leading_trivia.push(trivia),
+                Scanned::Nothing => {}
+            }
+        }
     }
 }
 
-pub fn lex_program(program: &str) -> Vec<Token> {
-    let mut lexer = Lexer::new();
-    lexer.scan(program);
+/// Identifiers start with a letter or '_' and continue with letters, digits
+/// and '_'. Letters and digits are taken from all of Unicode, which is a close
+/// approximation of the XID_Start and XID_Continue classes.
+pub fn is_identifier_start(c: char) -> bool {
+    c == '_' || c.is_alphabetic()
+}
 
-    return lexer.tokens;
+pub fn is_identifier_continue(c: char) -> bool {
+    c == '_' || c.is_alphanumeric()
+}
+
+/// Parses an integer literal as written in the source: decimal, or hexadecimal,
+/// binary and octal with a `0x`, `0b` or `0o` prefix, with `_` allowed as a
+/// separator after the first digit.
+pub fn parse_integer(literal: &str) -> Result<i64, LexErrorKind> {
+    let magnitude = parse_integer_magnitude(literal)?;
+    i64::try_from(magnitude).map_err(|_| LexErrorKind::IntegerOverflow(literal.to_string()))
+}
+
+/// Like `parse_integer`, but also accepts 2^63, which only fits in 64 bits
+/// once negated. The lexer lets it through for the parser to fold into
+/// `i64::MIN` when it follows a minus sign.
+pub fn parse_integer_magnitude(literal: &str) -> Result<u64, LexErrorKind> {
+    let (radix, digits) = match literal.get(..2) {
+        Some("0x") => (16, &literal[2..]),
+        Some("0b") => (2, &literal[2..]),
+        Some("0o") => (8, &literal[2..]),
+        _ => (10, literal),
+    };
+
+    if digits.starts_with('_') || digits.is_empty() {
+        return Err(LexErrorKind::MalformedNumber(literal.to_string()));
+    }
+    if let Some(digit) = digits.chars().find(|c| *c != '_' && !c.is_digit(radix)) {
+        return Err(LexErrorKind::InvalidDigit(digit, radix));
+    }
+
+    match u64::from_str_radix(&without_separators(digits), radix) {
+        Ok(magnitude) if magnitude <= i64::MIN.unsigned_abs() => Ok(magnitude),
+        _ => Err(LexErrorKind::IntegerOverflow(literal.to_string())),
+    }
+}
+
+/// Parses a float literal such as `3.14`, `1e-3` or `2.5E10`, with `_`
+/// allowed as a separator between digits.
+pub fn parse_float(literal: &str) -> Result<f64, LexErrorKind> {
+    if let Some(digit) = literal.chars().find(|c| !c.is_ascii_digit() && !matches!(c, '_' | '.' | 'e' | 'E' | '+' | '-')) {
+        return Err(LexErrorKind::InvalidDigit(digit, 10));
+    }
+
+    match without_separators(literal).parse::<f64>() {
+        Ok(value) if value.is_finite() => Ok(value),
+        Ok(_) => Err(LexErrorKind::FloatOverflow(literal.to_string())),
+        Err(_) => Err(LexErrorKind::MalformedNumber(literal.to_string())),
+    }
+}
+
+fn without_separators(digits: &str) -> Cow<'_, str> {
+    if digits.contains('_') {
+        Cow::Owned(digits.replace('_', ""))
+    } else {
+        Cow::Borrowed(digits)
+    }
+}
+
+/// Decodes the escape sequences in the contents of a string literal, i.e. the
+/// raw source without its quotes. Invalid escapes are reported to `on_error`
+/// with their byte range in `raw` and left out of the result.
+pub fn unescape(raw: &str, mut on_error: impl FnMut(Range<usize>, LexErrorKind)) -> String {
+    let mut value = String::with_capacity(raw.len());
+    let mut chars = raw.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        if c != '\\' {
+            value.push(c);
+            continue;
+        }
+
+        let decoded = match chars.next() {
+            Some((_, 'n')) => Some('\n'),
+            Some((_, 't')) => Some('\t'),
+            Some((_, 'r')) => Some('\r'),
+            Some((_, '0')) => Some('\0'),
+            Some((_, '"')) => Some('"'),
+            Some((_, '\'')) => Some('\''),
+            Some((_, '{')) => Some('{'),
+            Some((_, '}')) => Some('}'),
+            Some((_, '\\')) => Some('\\'),
+            Some((_, 'x')) => {
+                let mut digits = String::new();
+                while digits.len() < 2 && chars.peek().is_some_and(|(_, d)| d.is_ascii_hexdigit()) {
+                    digits.push(chars.next().unwrap().1);
+                }
+                match u8::from_str_radix(&digits, 16) {
+                    Ok(byte) if digits.len() == 2 && byte <= 0x7f => Some(byte as char),
+                    _ => None,
+                }
+            }
+            Some((_, 'u')) => {
+                let mut digits = String::new();
+                let opened = chars.next_if(|(_, d)| *d == '{').is_some();
+                while opened && chars.peek().is_some_and(|(_, d)| d.is_ascii_hexdigit()) {
+                    digits.push(chars.next().unwrap().1);
+                }
+                let closed = opened && chars.next_if(|(_, d)| *d == '}').is_some();
+                match u32::from_str_radix(&digits, 16) {
+                    Ok(code) if closed && digits.len() <= 6 => char::from_u32(code),
+                    _ => None,
+                }
+            }
+            _ => None,
+        };
+
+        match decoded {
+            Some(decoded) => value.push(decoded),
+            None => {
+                let end = chars.peek().map_or(raw.len(), |(i, _)| *i);
+                on_error(start..end, LexErrorKind::InvalidEscape(raw[start..end].to_string()));
+            }
+        }
+    }
+
+    value
+}
+
+/// Lexes the whole program, collecting every error instead of stopping at the
+/// first one. The tokens outlive `program`, so they share a copy of it.
+pub fn lex_program(program: &str) -> Result<Vec<Token>, Vec<LexError>> {
+    lex_file("", Rc::from(program))
+}
+
+/// Like `lex_program`, but every span records `file` as its origin, and the
+/// tokens share `program` itself rather than a copy.
+pub fn lex_file(file: &str, program: Rc<str>) -> Result<Vec<Token>, Vec<LexError>> {
+    collect_tokens(Lexer::new(file, program))
+}
+
+/// Like `lex_program`, but keeps whitespace and comments as trivia on the
+/// tokens and ends with an `Eof` token, so that `rebuild_source` gives back
+/// the program unchanged.
+pub fn lex_program_lossless(program: &str) -> Result<Vec<Token>, Vec<LexError>> {
+    collect_tokens(Lexer::new("", Rc::from(program)).with_trivia())
+}
+
+/// Concatenates the source text of a token stream lexed with trivia.
+pub fn rebuild_source(tokens: &[Token]) -> String {
+    let mut source = String::new();
+    for token in tokens {
+        for trivia in &token.leading_trivia {
+            source.push_str(&trivia.text);
+        }
+        source.push_str(&token.literal);
+        for trivia in &token.trailing_trivia {
+            source.push_str(&trivia.text);
+        }
+    }
+    source
+}
+
+fn collect_tokens(lexer: Lexer) -> Result<Vec<Token>, Vec<LexError>> {
+    let mut tokens = Vec::new();
+    let mut errors = Vec::new();
+    for result in lexer {
+        match result {
+            Ok(token) => tokens.push(token),
+            Err(error) => errors.push(error),
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(tokens)
+    } else {
+        Err(errors)
+    }
 }
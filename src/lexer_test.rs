@@ -1,125 +1,434 @@
 use super::*;
 use crate::tokens::Token;
 use crate::tokens::TokenType;
-use lexer::lex_program;
+use crate::tokens::TriviaKind;
+use std::rc::Rc;
+use lexer::{lex_file, lex_program, lex_program_lossless, rebuild_source, LexErrorKind, Lexer};
 
-fn tokens_to_literals(tokens: &Vec<Token>) -> Vec<String> {
-    tokens.into_iter().map(|token| token.literal.clone()).collect()
+fn tokens_to_literals(tokens: &[Token]) -> Vec<String> {
+    tokens.iter().map(|token| token.literal.to_string()).collect()
 }
 
 #[test]
 fn empty_string_produces_empty_list_of_tokens() {
-    let tokens = lex_program("");
+    let tokens = lex_program("").unwrap();
     assert_eq!(0, tokens.len());
 }
 
 #[test]
 fn token_assignment() {
-    let tokens = lex_program("=");
+    let tokens = lex_program("=").unwrap();
     assert_eq!(["="], &tokens_to_literals(&tokens)[..]);
 }
 
 #[test]
 fn token_plus() {
-    let tokens = lex_program("+");
+    let tokens = lex_program("+").unwrap();
     assert_eq!(["+"], &tokens_to_literals(&tokens)[..]);
 }
 
 #[test]
 fn token_minus() {
-    let tokens = lex_program("-");
+    let tokens = lex_program("-").unwrap();
     assert_eq!(["-"], &tokens_to_literals(&tokens)[..]);
 }
 
 #[test]
 fn token_asterisk() {
-    let tokens = lex_program("*");
+    let tokens = lex_program("*").unwrap();
     assert_eq!(["*"], &tokens_to_literals(&tokens)[..]);
 }
 
 #[test]
 fn token_equals() {
-    let tokens = lex_program("==");
+    let tokens = lex_program("==").unwrap();
     assert_eq!(["=="], &tokens_to_literals(&tokens)[..]);
 }
 
 #[test]
-#[should_panic]
 fn invalid_token_should_raise_error() {
-    let tokens = lex_program("=%");
-    assert_eq!(0, tokens.len());
+    let errors = lex_program("=$").unwrap_err();
+    assert_eq!(1, errors.len());
+    assert_eq!(LexErrorKind::UnrecognizedChar('$'), errors[0].kind);
+    assert_eq!((1, 1, 1, 2), (errors[0].span.offset, errors[0].span.len, errors[0].span.line, errors[0].span.column));
+    assert_eq!("1:2: unrecognized character '$'", errors[0].to_string());
+}
+
+#[test]
+fn all_errors_are_reported() {
+    let errors = lex_program("let a = 12ab;\n$ b = 3 @ \"open").unwrap_err();
+    let kinds: Vec<LexErrorKind> = errors.iter().map(|error| error.kind.clone()).collect();
+    assert_eq!(
+        [LexErrorKind::InvalidDigit('a', 10),
+            LexErrorKind::UnrecognizedChar('$'),
+            LexErrorKind::UnrecognizedChar('@'),
+            LexErrorKind::UnterminatedString],
+        &kinds[..]
+    );
+    let positions: Vec<(usize, usize)> = errors.iter().map(|error| (error.span.line, error.span.column)).collect();
+    assert_eq!([(1, 9), (2, 1), (2, 9), (2, 11)], &positions[..]);
+}
+
+#[test]
+fn unterminated_string() {
+    let errors = lex_program(r#"print("never closed"#).unwrap_err();
+    assert_eq!(1, errors.len());
+    assert_eq!(LexErrorKind::UnterminatedString, errors[0].kind);
+    assert_eq!((6, 13), (errors[0].span.offset, errors[0].span.len));
 }
 
 #[test]
 fn several_tokens() {
-    let tokens = lex_program(" + - ==  ;; *");
+    let tokens = lex_program(" + - ==  ;; *").unwrap();
     assert_eq!(["+", "-", "==", ";", ";", "*"], &tokens_to_literals(&tokens)[..]);
 }
 
 #[test]
 fn literal() {
-    let tokens = lex_program("banana");
+    let tokens = lex_program("banana").unwrap();
     assert_eq!(["banana"], &tokens_to_literals(&tokens)[..]);
 }
 
 #[test]
 fn literal_and_token() {
-    let tokens = lex_program("mango=");
+    let tokens = lex_program("mango=").unwrap();
     assert_eq!(["mango", "="], &tokens_to_literals(&tokens)[..]);
 }
 
+#[test]
+fn identifiers_with_underscores_and_digits() {
+    let tokens = lex_program("my_var _tmp __init__ x2 a_1_b _ fn_name").unwrap();
+    assert_eq!(["my_var", "_tmp", "__init__", "x2", "a_1_b", "_", "fn_name"], &tokens_to_literals(&tokens)[..]);
+    assert!(tokens.iter().all(|token| token.token_type == TokenType::Identifier));
+}
+
+#[test]
+fn unicode_identifiers() {
+    let tokens = lex_program("let größe = 日本語 + ñandú_2;").unwrap();
+    assert_eq!(["let", "größe", "=", "日本語", "+", "ñandú_2", ";"], &tokens_to_literals(&tokens)[..]);
+    assert_eq!((4, 7, 1, 5), (tokens[1].span.offset, tokens[1].span.len, tokens[1].span.line, tokens[1].span.column));
+    assert_eq!((1, 17), (tokens[4].span.line, tokens[4].span.column));
+}
+
+#[test]
+fn identifier_cannot_start_with_a_digit() {
+    let errors = lex_program("2fast").unwrap_err();
+    assert_eq!(LexErrorKind::InvalidDigit('f', 10), errors[0].kind);
+}
+
 #[test]
 fn two_literals() {
-    let tokens = lex_program("t omate");
+    let tokens = lex_program("t omate").unwrap();
     assert_eq!(["t", "omate"], &tokens_to_literals(&tokens)[..]);
 }
 
 #[test]
 fn literal_number_and_string() {
-    let tokens = lex_program(r#" banana 1337 "kiwi" "#);
-    assert_eq!(["banana", "1337", "kiwi"], &tokens_to_literals(&tokens)[..]);
+    let tokens = lex_program(r#" banana 1337 "kiwi" "#).unwrap();
+    assert_eq!(["banana", "1337", "\"kiwi\""], &tokens_to_literals(&tokens)[..]);
 }
 
 #[test]
 fn complex_expression() {
-    let tokens = lex_program("radio = pi*3 + 2;");
+    let tokens = lex_program("radio = pi*3 + 2;").unwrap();
     assert_eq!(["radio", "=", "pi", "*", "3", "+", "2", ";"], &tokens_to_literals(&tokens)[..]);
 }
 
+#[test]
+fn arithmetic_logical_and_bitwise_operators() {
+    let tokens = lex_program("a/b%c && d||e & f|g ^ ~h << 2 >> 1").unwrap();
+    assert_eq!(["a", "/", "b", "%", "c", "&&", "d", "||", "e", "&", "f", "|", "g", "^", "~", "h", "<<", "2", ">>", "1"],
+               &tokens_to_literals(&tokens)[..]);
+    let token_types: Vec<TokenType> = tokens.iter().map(|token| token.token_type).collect();
+    assert_eq!(
+        [TokenType::Slash, TokenType::Percent, TokenType::And, TokenType::Or, TokenType::Ampersand,
+            TokenType::Pipe, TokenType::Caret, TokenType::Tilde, TokenType::Lshift, TokenType::Rshift],
+        &token_types.into_iter().filter(|t| *t != TokenType::Identifier && *t != TokenType::Number).collect::<Vec<_>>()[..]
+    );
+}
+
+#[test]
+fn operators_use_maximal_munch() {
+    let tokens = lex_program("&&& ||| <<= >>= <<< >>>").unwrap();
+    assert_eq!(["&&", "&", "||", "|", "<<", "=", ">>", "=", "<<", "<", ">>", ">"], &tokens_to_literals(&tokens)[..]);
+}
+
 #[test]
 fn complex_expression_comparisons() {
-    let tokens = lex_program("4 < 19>2 >= !1 <= 1!=10");
+    let tokens = lex_program("4 < 19>2 >= !1 <= 1!=10").unwrap();
     assert_eq!(["4", "<", "19", ">", "2", ">=", "!", "1", "<=", "1", "!=", "10"],
                &tokens_to_literals(&tokens)[..]);
 }
 
 #[test]
 fn parentheses_and_braces() {
-    let tokens = lex_program("((2)) == !{{4}}");
+    let tokens = lex_program("((2)) == !{{4}}").unwrap();
     assert_eq!(["(", "(", "2", ")", ")", "==", "!", "{", "{", "4", "}", "}"],
                &tokens_to_literals(&tokens)[..]);
 }
 
+#[test]
+fn commas() {
+    let tokens = lex_program("f(a, b,c)").unwrap();
+    assert_eq!(["f", "(", "a", ",", "b", ",", "c", ")"], &tokens_to_literals(&tokens)[..]);
+    assert_eq!(TokenType::Comma, tokens[3].token_type);
+}
+
 #[test]
 fn single_letter_as_string() {
-    let tokens = lex_program(r#""f""#);
-    assert_eq!(["f"], &tokens_to_literals(&tokens)[..]);
+    let tokens = lex_program(r#""f""#).unwrap();
+    assert_eq!([r#""f""#], &tokens_to_literals(&tokens)[..]);
 }
 
 #[test]
 fn string_with_spaces() {
-    let tokens = lex_program(r#""canada is a nice country""#);
-    assert_eq!(["canada is a nice country"], &tokens_to_literals(&tokens)[..]);
+    let tokens = lex_program(r#""canada is a nice country""#).unwrap();
+    assert_eq!([r#""canada is a nice country""#], &tokens_to_literals(&tokens)[..]);
+}
+#[test]
+fn string_with_punctuation() {
+    let tokens = lex_program(r#"print("answer is: a,b (c)!");"#).unwrap();
+    assert_eq!(["print", "(", r#""answer is: a,b (c)!""#, ")", ";"], &tokens_to_literals(&tokens)[..]);
 }
+
+#[test]
+fn string_escapes() {
+    let tokens = lex_program(r#""tab\tquote\"back\\slash\0nul\x41\u{e9}\u{1F600}\n""#).unwrap();
+    assert_eq!(1, tokens.len());
+    assert_eq!(r#""tab\tquote\"back\\slash\0nul\x41\u{e9}\u{1F600}\n""#, tokens[0].literal);
+    assert_eq!("tab\tquote\"back\\slash\0nulA\u{e9}\u{1F600}\n", tokens[0].to_unescaped());
+}
+
+#[test]
+fn invalid_string_escapes() {
+    let errors = lex_program(r#"x = "\q \x8 \xff \u{110000} \u{12";"#).unwrap_err();
+    let escapes: Vec<(LexErrorKind, usize, usize)> = errors.iter()
+        .map(|error| (error.kind.clone(), error.span.offset, error.span.column))
+        .collect();
+    assert_eq!(
+        [(LexErrorKind::InvalidEscape("\\q".to_string()), 5, 6),
+            (LexErrorKind::InvalidEscape("\\x8".to_string()), 8, 9),
+            (LexErrorKind::InvalidEscape("\\xff".to_string()), 12, 13),
+            (LexErrorKind::InvalidEscape("\\u{110000}".to_string()), 17, 18),
+            (LexErrorKind::InvalidEscape("\\u{12".to_string()), 28, 29)],
+        &escapes[..]
+    );
+}
+
+#[test]
+fn strings_can_span_lines() {
+    let tokens = lex_program("\"first\n  second\" x \"a\n\\q\"").unwrap_err();
+    assert_eq!(LexErrorKind::InvalidEscape("\\q".to_string()), tokens[0].kind);
+    assert_eq!((3, 1), (tokens[0].span.line, tokens[0].span.column));
+
+    let tokens = lex_program("\"first\n  second\" x").unwrap();
+    assert_eq!(["\"first\n  second\"", "x"], &tokens_to_literals(&tokens)[..]);
+    assert_eq!("first\n  second", tokens[0].to_unescaped());
+    assert_eq!((2, 11), (tokens[1].span.line, tokens[1].span.column));
+}
+
+#[test]
+fn raw_strings() {
+    let tokens = lex_program(r###"r"C:\new\{x}" r#"say "hi""# r##"a "# b"## r"" rust"###).unwrap();
+    assert_eq!([r#"r"C:\new\{x}""#, r###"r#"say "hi""#"###, r###"r##"a "# b"##"###, r#"r"""#, "rust"],
+               &tokens_to_literals(&tokens)[..]);
+    let types: Vec<TokenType> = tokens.iter().map(|token| token.token_type).collect();
+    assert_eq!([TokenType::RawString, TokenType::RawString, TokenType::RawString, TokenType::RawString,
+                TokenType::Identifier], &types[..]);
+    assert_eq!(r"C:\new\{x}", tokens[0].to_unescaped());
+    assert_eq!(r#"say "hi""#, tokens[1].to_unescaped());
+    assert_eq!(r##"a "# b"##, tokens[2].to_unescaped());
+    assert_eq!("", tokens[3].to_unescaped());
+}
+
+#[test]
+fn multi_line_raw_strings() {
+    let source = "let help = r#\"\nusage: vvdc \"file\"\n\"#;\nhelp;";
+    let tokens = lex_program(source).unwrap();
+    assert_eq!("\nusage: vvdc \"file\"\n", tokens[3].to_unescaped());
+    assert_eq!((1, 12, 25), (tokens[3].span.line, tokens[3].span.column, tokens[3].span.len));
+    assert_eq!((3, 3), (tokens[4].span.line, tokens[4].span.column));
+    assert_eq!((4, 1), (tokens[5].span.line, tokens[5].span.column));
+    assert_eq!(source, rebuild_source(&lex_program_lossless(source).unwrap()));
+}
+
+#[test]
+fn unterminated_raw_string() {
+    let errors = lex_program("r#\"never \" closed\n").unwrap_err();
+    assert_eq!(1, errors.len());
+    assert_eq!(LexErrorKind::UnterminatedString, errors[0].kind);
+    assert_eq!((0, 18), (errors[0].span.offset, errors[0].span.len));
+}
+
+#[test]
+fn interpolated_strings() {
+    let tokens = lex_program(r#"print("x is {x}, y is {y}!");"#).unwrap();
+    assert_eq!(["print", "(", r#""x is {"#, "x", "}, y is {", "y", r#"}!""#, ")", ";"],
+               &tokens_to_literals(&tokens)[..]);
+    let types: Vec<TokenType> = tokens[2..7].iter().map(|token| token.token_type).collect();
+    assert_eq!([TokenType::StringHead, TokenType::Identifier, TokenType::StringMiddle,
+                TokenType::Identifier, TokenType::StringTail], &types[..]);
+    assert_eq!(["x is ", ", y is ", "!"], [tokens[2].to_unescaped(), tokens[4].to_unescaped(), tokens[6].to_unescaped()]);
+}
+
+#[test]
+fn interpolation_can_hold_braces_and_strings() {
+    let tokens = lex_program(r#""a{ {1} }b{"in{2}"}c""#).unwrap();
+    assert_eq!([r#""a{"#, "{", "1", "}", "}b{", r#""in{"#, "2", r#"}""#, r#"}c""#], &tokens_to_literals(&tokens)[..]);
+}
+
+#[test]
+fn escaped_braces_are_not_interpolated() {
+    let tokens = lex_program(r#""\{x\}""#).unwrap();
+    assert_eq!(1, tokens.len());
+    assert_eq!(TokenType::String, tokens[0].token_type);
+    assert_eq!("{x}", tokens[0].to_unescaped());
+
+    let tokens = lex_program(r#""\u{41}{x}""#).unwrap();
+    assert_eq!([r#""\u{41}{"#, "x", r#"}""#], &tokens_to_literals(&tokens)[..]);
+}
+
+#[test]
+fn unterminated_interpolation() {
+    let errors = lex_program(r#"print("x is {x);"#).unwrap_err();
+    assert_eq!(1, errors.len());
+    assert_eq!(LexErrorKind::UnterminatedInterpolation, errors[0].kind);
+    assert_eq!((6, 10), (errors[0].span.offset, errors[0].span.len));
+}
+
+#[test]
+fn lossless_lexing_of_interpolated_strings() {
+    let source = "print(\"sum: { a /* first */ } and {b}\"); // done\n";
+    let tokens = lex_program_lossless(source).unwrap();
+    assert_eq!(source, rebuild_source(&tokens));
+}
+
+#[test]
+fn integer_literals() {
+    let tokens = lex_program("42 1_000_000 0x2A 0xdead_BEEF 0b1010_1010 0o755 9223372036854775807").unwrap();
+    assert_eq!(["42", "1_000_000", "0x2A", "0xdead_BEEF", "0b1010_1010", "0o755", "9223372036854775807"],
+               &tokens_to_literals(&tokens)[..]);
+    let values: Vec<i64> = tokens.iter().map(|token| token.to_numeric()).collect();
+    assert_eq!([42, 1_000_000, 42, 0xdead_beef, 0b1010_1010, 0o755, i64::MAX], &values[..]);
+}
+
+#[test]
+fn invalid_integer_literals() {
+    let errors = lex_program("99999999999999999999 12abc 0b102 0o8 0x 0x_1 0xfg 0x8000000000000001").unwrap_err();
+    let kinds: Vec<LexErrorKind> = errors.iter().map(|error| error.kind.clone()).collect();
+    assert_eq!(
+        [LexErrorKind::IntegerOverflow("99999999999999999999".to_string()),
+            LexErrorKind::InvalidDigit('a', 10),
+            LexErrorKind::InvalidDigit('2', 2),
+            LexErrorKind::InvalidDigit('8', 8),
+            LexErrorKind::MalformedNumber("0x".to_string()),
+            LexErrorKind::MalformedNumber("0x_1".to_string()),
+            LexErrorKind::InvalidDigit('g', 16),
+            LexErrorKind::IntegerOverflow("0x8000000000000001".to_string())],
+        &kinds[..]
+    );
+    assert_eq!((21, 5), (errors[1].span.offset, errors[1].span.len));
+    assert_eq!("1:1: integer literal '99999999999999999999' does not fit in 64 bits", errors[0].to_string());
+}
+
+#[test]
+fn char_literals() {
+    let tokens = lex_program(r#"'a' '\n' '\'' '\\' '\x41' '\u{e9}' 'ñ' '\"' '\0'"#).unwrap();
+    assert!(tokens.iter().all(|token| token.token_type == TokenType::Char));
+    assert_eq!([r"'a'", r"'\n'", r"'\''", r"'\\'", r"'\x41'", r"'\u{e9}'", "'ñ'", r#"'\"'"#, r"'\0'"],
+               &tokens_to_literals(&tokens)[..]);
+    let values: Vec<char> = tokens.iter().map(|token| token.to_char()).collect();
+    assert_eq!(['a', '\n', '\'', '\\', 'A', 'é', 'ñ', '"', '\0'], &values[..]);
+}
+
+#[test]
+fn invalid_char_literals() {
+    let errors = lex_program("'' 'ab' '\\q' 'x\n1").unwrap_err();
+    let kinds: Vec<LexErrorKind> = errors.iter().map(|error| error.kind.clone()).collect();
+    assert_eq!(
+        [LexErrorKind::InvalidCharLiteral("''".to_string()),
+            LexErrorKind::InvalidCharLiteral("'ab'".to_string()),
+            LexErrorKind::InvalidEscape("\\q".to_string()),
+            LexErrorKind::UnterminatedChar],
+        &kinds[..]
+    );
+    assert_eq!((3, 4), (errors[1].span.offset, errors[1].span.len));
+}
+
+#[test]
+fn float_literals() {
+    let tokens = lex_program("3.25 1e-3 2.5e10 1E+2 0.5 1_000.25 6.02e2_3").unwrap();
+    assert!(tokens.iter().all(|token| token.token_type == TokenType::Float));
+    let values: Vec<f64> = tokens.iter().map(|token| token.to_float()).collect();
+    assert_eq!([3.25, 1e-3, 2.5e10, 1e2, 0.5, 1000.25, 6.02e23], &values[..]);
+}
+
+#[test]
+fn numbers_next_to_dots_and_exponents() {
+    let tokens = lex_program("1 12 0xe1 0b1 1.5").unwrap();
+    let token_types: Vec<TokenType> = tokens.iter().map(|token| token.token_type).collect();
+    assert_eq!([TokenType::Number, TokenType::Number, TokenType::Number, TokenType::Number, TokenType::Float],
+               &token_types[..]);
+    assert_eq!(0xe1, tokens[2].to_numeric());
+}
+
+#[test]
+fn invalid_float_literals() {
+    let errors = lex_program("1.5x 1e999 2.0e 1e").unwrap_err();
+    let kinds: Vec<LexErrorKind> = errors.iter().map(|error| error.kind.clone()).collect();
+    assert_eq!(
+        [LexErrorKind::InvalidDigit('x', 10),
+            LexErrorKind::FloatOverflow("1e999".to_string()),
+            LexErrorKind::MalformedNumber("2.0e".to_string()),
+            LexErrorKind::InvalidDigit('e', 10)],
+        &kinds[..]
+    );
+}
+
 #[test]
 fn newlines() {
-    let tokens = lex_program("3*\n2 + \n 3;");
+    let tokens = lex_program("3*\n2 + \n 3;").unwrap();
     assert_eq!(["3", "*", "2", "+", "3", ";"], &tokens_to_literals(&tokens)[..]);
 }
 
+#[test]
+fn line_comments() {
+    let tokens = lex_program("// leading comment\nreturn 4; // trailing / comment\n2 // no newline").unwrap();
+    assert_eq!(["return", "4", ";", "2"], &tokens_to_literals(&tokens)[..]);
+    assert_eq!(2, tokens[0].span.line);
+}
+
+#[test]
+fn block_comments() {
+    let tokens = lex_program("1 /* one\nline * two */ + /**/2").unwrap();
+    assert_eq!(["1", "+", "2"], &tokens_to_literals(&tokens)[..]);
+    assert_eq!((2, 15), (tokens[1].span.line, tokens[1].span.column));
+}
+
+#[test]
+fn nested_block_comments() {
+    let tokens = lex_program("1 /* outer /* inner */ still outer */ 2").unwrap();
+    assert_eq!(["1", "2"], &tokens_to_literals(&tokens)[..]);
+}
+
+#[test]
+fn unterminated_block_comment() {
+    let errors = lex_program("1 /* outer /* inner */ 2").unwrap_err();
+    assert_eq!(1, errors.len());
+    assert_eq!(LexErrorKind::UnterminatedComment, errors[0].kind);
+    assert_eq!((2, 22), (errors[0].span.offset, errors[0].span.len));
+}
+
+#[test]
+fn hash_is_not_a_comment() {
+    let errors = lex_program("1 / 2 # 3").unwrap_err();
+    let kinds: Vec<LexErrorKind> = errors.iter().map(|error| error.kind.clone()).collect();
+    assert_eq!([LexErrorKind::UnrecognizedChar('#')], &kinds[..]);
+}
+
 #[test]
 fn keywords() {
-    let tokens: Vec<Token> = lex_program("if print while return let fn");
+    let tokens: Vec<Token> = lex_program("if print while return let fn").unwrap();
     assert_eq!(["if", "print", "while", "return", "let", "fn"], &tokens_to_literals(&tokens)[..]);
     let token_types: Vec<TokenType> = tokens.into_iter().map(|token| token.token_type).collect();
     assert_eq!(
@@ -134,6 +443,38 @@ fn keywords() {
     );
 }
 
+#[test]
+fn reserved_keywords() {
+    let tokens = lex_program("else for in break continue const import extern struct").unwrap();
+    let token_types: Vec<TokenType> = tokens.iter().map(|token| token.token_type).collect();
+    assert_eq!(
+        [TokenType::KeywordElse,
+            TokenType::KeywordFor,
+            TokenType::KeywordIn,
+            TokenType::KeywordBreak,
+            TokenType::KeywordContinue,
+            TokenType::KeywordConst,
+            TokenType::KeywordImport,
+            TokenType::KeywordExtern,
+            TokenType::KeywordStruct
+        ],
+        &token_types[..]
+    );
+}
+
+#[test]
+fn keywords_are_whole_words() {
+    let tokens = lex_program("elsewhere format inner breaking constant imports external structure").unwrap();
+    assert!(tokens.iter().all(|token| token.token_type == TokenType::Identifier));
+}
+
+#[test]
+fn boolean_keywords() {
+    let tokens = lex_program("true false truthy").unwrap();
+    let token_types: Vec<TokenType> = tokens.iter().map(|token| token.token_type).collect();
+    assert_eq!([TokenType::KeywordTrue, TokenType::KeywordFalse, TokenType::Identifier], &token_types[..]);
+}
+
 #[test]
 fn sample_program() {
     let tokens = lex_program(
@@ -150,10 +491,171 @@ fn sample_program() {
                 return 0;
             }
             "#,
-    );
+    ).unwrap();
 
     assert_eq!(["fn","square","(","x",")","{","return","x","*","x",";","}",
                "fn","main","(",")","{","let","y","=","square","(","4",")",";",
-               "while","y",">=","12","{","print","(","answer is ","y",")",";","}",
+               "while","y",">=","12","{","print","(","\"answer is \"","y",")",";","}",
                "return","0",";","}"], &tokens_to_literals(&tokens)[..]);
 }
+
+#[test]
+fn spans() {
+    let tokens = lex_file("spans.vvdl", Rc::from("return 42;\n  x >= \"hello\";")).unwrap();
+    let spans: Vec<(usize, usize, usize, usize)> = tokens.iter()
+        .map(|token| (token.span.offset, token.span.len, token.span.line, token.span.column))
+        .collect();
+    assert_eq!(
+        [(0, 6, 1, 1), (7, 2, 1, 8), (9, 1, 1, 10),
+            (13, 1, 2, 3), (15, 2, 2, 5), (18, 7, 2, 8), (25, 1, 2, 15)],
+        &spans[..]
+    );
+    assert_eq!("spans.vvdl:2:8", tokens[5].span.to_string());
+}
+
+#[test]
+fn lexer_streams_tokens_and_errors_in_order() {
+    let mut lexer = Lexer::new("", Rc::from("x = 1 $ \"\\q\" 2")).peekable();
+    assert_eq!("x", lexer.peek().unwrap().as_ref().unwrap().literal);
+    assert_eq!("x", lexer.next().unwrap().unwrap().literal);
+    assert_eq!("=", lexer.next().unwrap().unwrap().literal);
+    assert_eq!("1", lexer.next().unwrap().unwrap().literal);
+    assert_eq!(LexErrorKind::UnrecognizedChar('$'), lexer.next().unwrap().unwrap_err().kind);
+    assert_eq!(r#""\q""#, lexer.next().unwrap().unwrap().literal);
+    assert_eq!(LexErrorKind::InvalidEscape("\\q".to_string()), lexer.next().unwrap().unwrap_err().kind);
+    assert_eq!("2", lexer.next().unwrap().unwrap().literal);
+    assert!(lexer.next().is_none());
+}
+
+#[test]
+fn tokens_share_the_source() {
+    let source: Rc<str> = Rc::from("let x = 1;");
+    let tokens = lex_file("", source.clone()).unwrap();
+    assert_eq!(source[4..].as_ptr(), tokens[1].literal.as_ptr());
+}
+
+#[test]
+fn lexer_is_lazy() {
+    let source = "1 ".repeat(1000);
+    let first_two: Vec<Token> = Lexer::new("", Rc::from(source)).take(2).map(Result::unwrap).collect();
+    assert_eq!(["1", "1"], &tokens_to_literals(&first_two)[..]);
+    assert_eq!(2, first_two[1].span.offset);
+}
+
+#[test]
+fn lossless_lexing_rebuilds_the_source() {
+    let source = "  // header\r\nfn main() { /* a /* nested */ block */\n\treturn \"x\\ty\" + 0xF_0; // done\n}\n\n// trailing comment";
+    let tokens = lex_program_lossless(source).unwrap();
+    assert_eq!(source, rebuild_source(&tokens));
+    assert_eq!(TokenType::Eof, tokens.last().unwrap().token_type);
+}
+
+#[test]
+fn lossless_lexing_of_trivia_only() {
+    for source in ["", "   ", "// only a comment", "\n\n/* */\n"] {
+        let tokens = lex_program_lossless(source).unwrap();
+        assert_eq!(1, tokens.len());
+        assert_eq!(source, rebuild_source(&tokens));
+    }
+}
+
+#[test]
+fn trivia_is_attached_to_tokens() {
+    let tokens = lex_program_lossless("// lead\nx = 1; // same line\n  y").unwrap();
+    assert_eq!(["x", "=", "1", ";", "y", ""], &tokens_to_literals(&tokens)[..]);
+
+    let kinds = |trivia: &[crate::tokens::Trivia]| trivia.iter().map(|t| (t.kind, t.text.to_string())).collect::<Vec<_>>();
+    assert_eq!(vec![(TriviaKind::LineComment, "// lead".to_string()), (TriviaKind::Whitespace, "\n".to_string())],
+               kinds(&tokens[0].leading_trivia));
+    assert_eq!(vec![(TriviaKind::Whitespace, " ".to_string())], kinds(&tokens[0].trailing_trivia));
+    assert_eq!(vec![(TriviaKind::Whitespace, " ".to_string()), (TriviaKind::LineComment, "// same line".to_string()),
+                    (TriviaKind::Whitespace, "\n".to_string())],
+               kinds(&tokens[3].trailing_trivia));
+    assert_eq!(vec![(TriviaKind::Whitespace, "  ".to_string())], kinds(&tokens[4].leading_trivia));
+    assert_eq!((3, 1), (tokens[4].leading_trivia[0].span.line, tokens[4].leading_trivia[0].span.column));
+}
+
+#[test]
+fn default_lexing_drops_trivia() {
+    let tokens = lex_program("// lead\nx = 1; // same line").unwrap();
+    assert_eq!(["x", "=", "1", ";"], &tokens_to_literals(&tokens)[..]);
+    assert!(tokens.iter().all(|token| token.leading_trivia.is_empty() && token.trailing_trivia.is_empty()));
+}
+
+#[test]
+fn tokens_as_json() {
+    let tokens = lex_file("dir/a.vvdl", Rc::from("x = 1;")).unwrap();
+    assert_eq!(
+        r#"{"type":"Identifier","literal":"x","span":{"file":"dir/a.vvdl","offset":0,"len":1,"line":1,"column":1}}"#,
+        tokens[0].to_json()
+    );
+    assert_eq!(
+        r#"{"type":"Number","literal":"1","span":{"file":"dir/a.vvdl","offset":4,"len":1,"line":1,"column":5}}"#,
+        tokens[2].to_json()
+    );
+}
+
+#[test]
+fn json_escapes_literals() {
+    let tokens = lex_program("\"say \\\"hi\\\"\\\\\" r\"tab\tline\nend\"").unwrap();
+    assert_eq!(
+        r#"{"type":"String","literal":"\"say \\\"hi\\\"\\\\\"","span":{"file":"","offset":0,"len":14,"line":1,"column":1}}"#,
+        tokens[0].to_json()
+    );
+    assert!(tokens[1].to_json().contains(r#""literal":"r\"tab\tline\nend\"""#));
+}
+
+#[test]
+fn columns_count_characters_after_multi_byte_text() {
+    let tokens = lex_program("\"ñé\" /* ü\n ö */ x // ß\n  'ä' y").unwrap();
+    let positions: Vec<(usize, usize)> = tokens.iter().map(|token| (token.span.line, token.span.column)).collect();
+    assert_eq!([(1, 1), (2, 7), (3, 3), (3, 7)], &positions[..]);
+    assert_eq!("'ä'", tokens[2].literal);
+}
+
+#[test]
+fn literals_are_compared_by_text() {
+    let tokens = lex_program("abc + abc").unwrap();
+    assert_eq!(tokens[0].literal, tokens[2].literal);
+    assert_eq!(crate::tokens::SharedStr::from("abc"), tokens[0].literal);
+    assert_eq!("abc", tokens[0].literal.to_string());
+}
+
+/// Throughput check on a generated multi-megabyte program. Run with
+/// `cargo test --release lexer_throughput -- --ignored --nocapture`.
+///
+/// Best of five runs on the same machine: the lexer that copied the source
+/// into a `Vec<char>` and gave every token its own `String` (e9f0027) did
+/// 21.7 MB/s on this program, the byte-oriented one sharing the source 41.1 MB/s.
+#[test]
+#[ignore]
+fn lexer_throughput() {
+    let mut source = String::new();
+    let mut i = 0;
+    while source.len() < 8 * 1024 * 1024 {
+        source.push_str(&format!(
+            "// routine number {i}\nfn compute_{i}(value) {{\n    let total_{i} = value * {i} + 0x{i:x} - value / 3.5;\n    \
+             /* keep looping */ while total_{i} >= 10 && !done {{ total_{i} = total_{i} - 1; }}\n    \
+             print(\"total is {{total_{i}}} for 'case' {i}\\n\");\n    return total_{i} % 'x';\n}}\n\n",
+            i = i
+        ));
+        i += 1;
+    }
+
+    let source: Rc<str> = Rc::from(source);
+    let runs = 5;
+    let mut best = std::time::Duration::MAX;
+    let mut count = 0;
+    for _ in 0..runs {
+        let started = std::time::Instant::now();
+        let tokens = lex_file("", source.clone()).unwrap();
+        best = best.min(started.elapsed());
+        count = tokens.len();
+    }
+    let megabytes = source.len() as f64 / (1024.0 * 1024.0);
+    println!(
+        "lexed {:.1} MB into {} tokens in {:?}: {:.1} MB/s",
+        megabytes, count, best, megabytes / best.as_secs_f64()
+    );
+}
+
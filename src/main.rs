@@ -4,30 +4,61 @@ pub mod parser;
 pub mod tokens;
 use std::{env, fs};
 use std::path::Path;
-use std::process::{Command};
+use std::process::{self, Command};
+use std::rc::Rc;
+
+/// What the compiler writes out, chosen with `--emit=<kind>`.
+#[derive(PartialEq)]
+enum Emit {
+    /// Assemble, link and run the program.
+    Program,
+    /// Print the tokens as JSON Lines and stop.
+    Tokens,
+}
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
-    let program_path = if args.len() > 1 {
-        args[1].clone()
-    } else {
-        "programs/test.vvdl".to_string()
+    let mut program_path = "programs/test.vvdl".to_string();
+    let mut emit = Emit::Program;
+    for arg in env::args().skip(1) {
+        match arg.strip_prefix("--emit=") {
+            Some("tokens") => emit = Emit::Tokens,
+            Some(kind) => {
+                eprintln!("error: unknown --emit kind '{}', expected 'tokens'", kind);
+                process::exit(2);
+            }
+            None => program_path = arg,
+        }
+    }
+
+    let code: Rc<str> = fs::read_to_string(program_path.clone()).expect("Unable to read file").into();
+    let tokens = match lexer::lex_file(&program_path, code.clone()) {
+        Ok(tokens) => tokens,
+        Err(errors) => {
+            for error in &errors {
+                eprintln!("error: {}", error);
+            }
+            process::exit(1);
+        }
     };
+    if emit == Emit::Tokens {
+        for token in &tokens {
+            println!("{}", token.to_json());
+        }
+        return;
+    }
+
     let program_name = Path::new(&program_path).file_stem().unwrap().to_str().unwrap();
     println!("Compiling {}", program_path);
     println!("Program name: {}", program_name);
-
-    let code = fs::read_to_string(program_path.clone()).expect("Unable to read file");
     println!("============ CODE ============");
     println!("{}", code);
     println!("============ TOKENS ============");
-    let tokens = lexer::lex_program(&code);
     println!("{:?}", tokens);
     println!("============ AST ============");
-    let expressions = parser::parse(tokens);
-    //println!("{:?}", &expressions);
+    let statements = parser::parse(tokens);
+    println!("{:?}", &statements);
     println!("============ ASSEMBLY ============");
-    let code = codegen::generate_code(expressions);
+    let code = codegen::generate_code(statements);
     println!("{}", code);
 
     println!("============ BUILDING ============");
@@ -37,7 +68,7 @@ fn main() {
 
     // Call nasm on .asm file to generate .o file
     let nasm_output = Command::new("nasm")
-        .args(&["-f elf64", &*asm_path])
+        .args(["-f elf64", &*asm_path])
         .status()
         .expect("failed to execute nasm");
     if nasm_output.success() {
@@ -50,7 +81,7 @@ fn main() {
     let executable_path = format!("programs/obj/{}", program_name);
     let obj_path = format!("{}.o", executable_path);
     let ld_output = Command::new("ld")
-        .args(&["-s", "-o", &*executable_path, &*obj_path])
+        .args(["-s", "-o", &*executable_path, &*obj_path])
         .status()
         .expect("failed to execute ld");
     if ld_output.success() {
@@ -1,289 +1,568 @@
-use std::fmt::Debug;
+use crate::tokens::Span;
+use crate::tokens::SharedStr;
 use crate::tokens::Token;
 use crate::tokens::TokenType;
 
-#[derive(Debug, PartialEq)]
-pub enum ExpressionType {
-    Identifier,
-    Number,
-    String,
-    Prefix,
-    Infix,
-    Return,
+/// An expression, which evaluates to a value. Every node keeps the span of
+/// source code it was parsed from, including its operands.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Identifier {
+        name: SharedStr,
+        span: Span,
+    },
+    Number {
+        value: i64,
+        span: Span,
+    },
+    Float {
+        value: f64,
+        span: Span,
+    },
+    /// A string literal, with its escapes already decoded.
+    String {
+        value: String,
+        span: Span,
+    },
+    Boolean {
+        value: bool,
+        span: Span,
+    },
+    /// A character literal, which evaluates to the character's code point.
+    Char {
+        value: char,
+        span: Span,
+    },
+    /// A string with interpolated expressions, such as `"x is {x}"`. Its parts
+    /// alternate between string literals, one per `StringHead`, `StringMiddle`
+    /// and `StringTail` token, and the expressions between them.
+    Interpolation {
+        parts: Vec<Expr>,
+        span: Span,
+    },
+    /// A call of the function `name`, such as `square(4)`.
+    Call {
+        name: SharedStr,
+        arguments: Vec<Expr>,
+        span: Span,
+    },
+    /// An expression in parentheses. Its span covers both of them.
+    Grouping {
+        expr: Box<Expr>,
+        span: Span,
+    },
+    Prefix {
+        operator: TokenType,
+        right: Box<Expr>,
+        span: Span,
+    },
+    Infix {
+        operator: TokenType,
+        left: Box<Expr>,
+        right: Box<Expr>,
+        span: Span,
+    },
 }
 
-pub trait Expression {
-    fn get_type(&self) -> ExpressionType;
-    fn as_str(&self) -> String;
-
-    fn literal(&self) -> Option<String> {
-        None
-    }
-
-    fn left(&self) -> Option<&BoxExpression> {
-        None
-    }
-
-    fn right(&self) -> Option<&BoxExpression> {
-        None
-    }
-}
-
-pub type BoxExpression = Box<dyn Expression>;
-
-#[derive(Debug, Clone)]
-pub struct IdentifierExpression {
-    pub token: Token,
-    pub value: i64,
-}
-
-impl Expression for IdentifierExpression {
-    fn get_type(&self) -> ExpressionType {
-        ExpressionType::Identifier
-    }
-
-    fn as_str(&self) -> String {
-        self.value.to_string()
-    }
-
-    fn literal(&self) -> Option<String> {
-        Some(self.token.literal.clone())
-    }
-}
-
-#[derive(Debug, Clone)]
-pub struct NumberLiteralExpression {
-    pub token: Token,
-    pub value: i64,
-}
-
-impl Expression for NumberLiteralExpression {
-    fn get_type(&self) -> ExpressionType {
-        ExpressionType::Number
-    }
-
-    fn as_str(&self) -> String {
-        self.value.to_string()
-    }
-
-    fn literal(&self) -> Option<String> {
-        Some(self.token.literal.clone())
+impl Expr {
+    /// Source code covered by this expression, including its operands.
+    pub fn span(&self) -> &Span {
+        match self {
+            Expr::Identifier { span, .. }
+            | Expr::Number { span, .. }
+            | Expr::Float { span, .. }
+            | Expr::String { span, .. }
+            | Expr::Boolean { span, .. }
+            | Expr::Char { span, .. }
+            | Expr::Interpolation { span, .. }
+            | Expr::Call { span, .. }
+            | Expr::Grouping { span, .. }
+            | Expr::Prefix { span, .. }
+            | Expr::Infix { span, .. } => span,
+        }
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct StringLiteralExpression {
-    pub token: Token,
-    pub value: String,
+/// A statement, which is run for its effect.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Stmt {
+    /// An expression whose value is thrown away, such as `f(x);`.
+    Expression {
+        expr: Expr,
+    },
+    /// `let name = value;`, which declares a new variable. A later `let` with
+    /// the same name shadows the earlier one.
+    Let {
+        name: SharedStr,
+        value: Expr,
+        span: Span,
+    },
+    /// `name = value;`, which assigns to a variable.
+    Assign {
+        name: SharedStr,
+        value: Expr,
+        span: Span,
+    },
+    Return {
+        value: Expr,
+        span: Span,
+    },
+    /// A function declaration, either `fn name(a, b) { ... }` or the bare
+    /// `name(a, b) { ... }`.
+    Function {
+        name: SharedStr,
+        parameters: Vec<SharedStr>,
+        body: Vec<Stmt>,
+        span: Span,
+    },
+    Print {
+        value: Expr,
+        span: Span,
+    },
+    /// `while condition { ... }`, which runs its body for as long as the
+    /// condition holds. Variables declared in the body go out of scope at its end.
+    While {
+        condition: Expr,
+        body: Vec<Stmt>,
+        span: Span,
+    },
 }
 
-impl Expression for StringLiteralExpression {
-    fn get_type(&self) -> ExpressionType {
-        ExpressionType::String
-    }
-
-    fn as_str(&self) -> String {
-        self.value.to_string()
-    }
-
-    fn literal(&self) -> Option<String> {
-        Some(self.token.literal.clone())
+impl Stmt {
+    /// Source code covered by this statement.
+    pub fn span(&self) -> &Span {
+        match self {
+            Stmt::Expression { expr } => expr.span(),
+            Stmt::Let { span, .. }
+            | Stmt::Assign { span, .. }
+            | Stmt::Return { span, .. }
+            | Stmt::Print { span, .. }
+            | Stmt::Function { span, .. }
+            | Stmt::While { span, .. } => span,
+        }
     }
 }
 
-pub struct PrefixExpression {
-    pub token: Token,
-    pub right: BoxExpression,
+/// Binding power of the prefix operators, which bind tighter than any infix one.
+const PREFIX_BINDING_POWER: u8 = 21;
+
+/// Left and right binding powers of the infix operators. An operator takes
+/// its operands away from its neighbours when its power is higher, and its
+/// right power being above its left one makes it left associative.
+fn infix_binding_power(token_type: TokenType) -> Option<(u8, u8)> {
+    let powers = match token_type {
+        TokenType::Or => (1, 2),
+        TokenType::And => (3, 4),
+        TokenType::Equals | TokenType::Unequal => (5, 6),
+        TokenType::Lt | TokenType::Gt | TokenType::Lteq | TokenType::Gteq => (7, 8),
+        TokenType::Pipe => (9, 10),
+        TokenType::Caret => (11, 12),
+        TokenType::Ampersand => (13, 14),
+        TokenType::Lshift | TokenType::Rshift => (15, 16),
+        TokenType::Plus | TokenType::Minus => (17, 18),
+        TokenType::Asterisk | TokenType::Slash | TokenType::Percent => (19, 20),
+        _ => return None,
+    };
+    Some(powers)
 }
 
-impl Debug for PrefixExpression {
-    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        write!(f, "PrefixExpression")
-    }
+pub struct Parser {
+    tokens: Vec<Token>,
+    statements: Vec<Stmt>,
+    token_index: usize,
 }
 
-impl Expression for PrefixExpression {
-    fn get_type(&self) -> ExpressionType {
-        ExpressionType::Prefix
+impl Parser {
+    fn new(tokens: Vec<Token>) -> Parser {
+        Parser {
+            tokens,
+            statements: Vec::new(),
+            token_index: 0,
+        }
     }
 
-    fn as_str(&self) -> String {
-        format!("{:?}", self.token.token_type)
+    pub fn parse(&mut self) {
+        while self.token_index < self.tokens.len() {
+            let (statement, advance) = self.parse_statement(self.token_index);
+            if let Some(statement) = statement {
+                self.statements.push(statement);
+            }
+            self.token_index += advance;
+        }
     }
 
-    fn right(&self) -> Option<&BoxExpression> {
-        Some(&self.right)
+    fn token_type_at(&self, index: usize) -> TokenType {
+        self.tokens.get(index).map_or(TokenType::None, |token| token.token_type)
     }
-}
-
-pub struct InfixExpression {
-    pub token: Token,
-    pub left: BoxExpression,
-    pub right: BoxExpression,
-}
 
-impl Debug for InfixExpression {
-    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        write!(f, "InfixExpression")
+    /// Whether the token at `index` is the integer literal 2^63, which is
+    /// only valid as the magnitude of `i64::MIN`.
+    fn is_integer_past_i64_max(&self, index: usize) -> bool {
+        self.token_type_at(index) == TokenType::Number
+            && crate::lexer::parse_integer(&self.tokens[index].literal).is_err()
     }
-}
 
-impl Expression for InfixExpression {
-    fn get_type(&self) -> ExpressionType {
-        ExpressionType::Infix
+    /// The token at `index`, which must be of type `token_type`. `what`
+    /// describes it in the error otherwise.
+    fn expect_token(&self, index: usize, token_type: TokenType, what: &str) -> &Token {
+        match self.tokens.get(index) {
+            Some(token) if token.token_type == token_type => token,
+            Some(token) => panic!("{}: expected {}, found '{}'", token.span, what, token.literal),
+            None => {
+                let last = &self.tokens[index - 1];
+                panic!("{}: expected {} after '{}'", last.span, what, last.literal)
+            }
+        }
     }
 
-    fn as_str(&self) -> String {
-        format!("{:?}", self.token.token_type)
+    /// Parses the right operand of the infix operator at `from`, whose left
+    /// operand was already parsed.
+    fn parse_infix_expression(&self, left: Expr, from: usize, right_power: u8) -> (Expr, usize) {
+        let (right, adv) = self.parse_operand(from + 1, right_power);
+        let infix = Expr::Infix {
+            operator: self.tokens[from].token_type,
+            span: left.span().to(right.span()),
+            left: Box::new(left),
+            right: Box::new(right),
+        };
+        (infix, adv + 1)
     }
 
-    fn left(&self) -> Option<&BoxExpression> {
-        Some(&self.left)
+    /// Parses the operand of an operator, which must be there.
+    fn parse_operand(&self, from: usize, min_power: u8) -> (Expr, usize) {
+        match self.parse_expression_with_power(from, min_power) {
+            (Some(expression), adv) => (expression, adv),
+            (None, _) => match self.tokens.get(from) {
+                Some(token) => panic!("{}: expected an expression, found '{}'", token.span, token.literal),
+                None => {
+                    let last = &self.tokens[from - 1];
+                    panic!("{}: expected an expression after '{}'", last.span, last.literal)
+                }
+            },
+        }
     }
 
-    fn right(&self) -> Option<&BoxExpression> {
-        Some(&self.right)
-    }
-}
-
-pub struct ReturnExpression {
-    pub token: Token,
-    pub value: BoxExpression,
-}
-
-impl Debug for ReturnExpression {
-    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        write!(f, "Return")
+    /// Parses a statement starting at `from`. Stray semicolons are skipped
+    /// and give no statement.
+    fn parse_statement(&self, from: usize) -> (Option<Stmt>, usize) {
+        let token = &self.tokens[from];
+        match token.token_type {
+            TokenType::Rparen => panic!("{}: unmatched ')'", token.span),
+            TokenType::Rbrace => panic!("{}: unmatched '}}'", token.span),
+            TokenType::Comma => panic!("{}: unexpected ','", token.span),
+            TokenType::KeywordFn => {
+                let (statement, adv) = self.parse_function(from + 1, token);
+                (Some(statement), 1 + adv)
+            }
+            TokenType::Identifier if self.is_bare_function(from) => {
+                let (statement, adv) = self.parse_function(from, token);
+                (Some(statement), adv)
+            }
+            TokenType::KeywordPrint => {
+                self.expect_token(from + 1, TokenType::Lparen, "'('");
+                let (value, adv) = self.parse_operand(from + 2, 0);
+                let close = self.expect_token(from + 2 + adv, TokenType::Rparen, "')' to close print");
+                let statement = Stmt::Print {
+                    span: token.span.to(&close.span),
+                    value,
+                };
+                (Some(statement), 3 + adv)
+            }
+            TokenType::KeywordLet => {
+                let name = self.expect_token(from + 1, TokenType::Identifier, "a variable name after 'let'");
+                self.expect_token(from + 2, TokenType::Assignment, "'='");
+                let (value, adv) = self.parse_operand(from + 3, 0);
+                let statement = Stmt::Let {
+                    name: name.literal.clone(),
+                    span: token.span.to(value.span()),
+                    value,
+                };
+                (Some(statement), 3 + adv)
+            }
+            TokenType::Identifier if self.token_type_at(from + 1) == TokenType::Assignment => {
+                let (value, adv) = self.parse_operand(from + 2, 0);
+                let statement = Stmt::Assign {
+                    name: token.literal.clone(),
+                    span: token.span.to(value.span()),
+                    value,
+                };
+                (Some(statement), 2 + adv)
+            }
+            TokenType::KeywordWhile => {
+                let (condition, adv) = self.parse_operand(from + 1, 0);
+                let (body, block_adv) = self.parse_block(from + 1 + adv);
+                let statement = Stmt::While {
+                    condition,
+                    body,
+                    span: token.span.to(&self.tokens[from + adv + block_adv].span),
+                };
+                (Some(statement), 1 + adv + block_adv)
+            }
+            TokenType::KeywordReturn => {
+                let (value, adv) = self.parse_operand(from + 1, 0);
+                let statement = Stmt::Return {
+                    span: token.span.to(value.span()),
+                    value,
+                };
+                (Some(statement), 1 + adv)
+            }
+            _ => {
+                let (expression, adv) = self.parse_expression_with_power(from, 0);
+                (expression.map(|expr| Stmt::Expression { expr }), adv)
+            }
+        }
     }
-}
 
-impl Expression for ReturnExpression {
-    fn get_type(&self) -> ExpressionType {
-        ExpressionType::Return
+    /// Whether the tokens at `from` start a function declaration without
+    /// `fn`, which unlike a call has only names in its parentheses and is
+    /// followed by a block.
+    fn is_bare_function(&self, from: usize) -> bool {
+        if self.token_type_at(from + 1) != TokenType::Lparen {
+            return false;
+        }
+        let mut index = from + 2;
+        while matches!(self.token_type_at(index), TokenType::Identifier | TokenType::Comma) {
+            index += 1;
+        }
+        self.token_type_at(index) == TokenType::Rparen && self.token_type_at(index + 1) == TokenType::Lbrace
     }
 
-    fn as_str(&self) -> String {
-        format!("{:?}", self.token.token_type)
+    /// Parses a function declaration from its name at `from` on. `start` is
+    /// the first token of the declaration.
+    fn parse_function(&self, from: usize, start: &Token) -> (Stmt, usize) {
+        let name = self.expect_token(from, TokenType::Identifier, "a function name");
+        self.expect_token(from + 1, TokenType::Lparen, "'(' after the function name");
+        let mut parameters: Vec<SharedStr> = Vec::new();
+        let mut index = from + 2;
+        if self.token_type_at(index) != TokenType::Rparen {
+            loop {
+                let parameter = self.expect_token(index, TokenType::Identifier, "a parameter name");
+                if parameters.contains(&parameter.literal) {
+                    panic!("{}: duplicate parameter '{}'", parameter.span, parameter.literal);
+                }
+                parameters.push(parameter.literal.clone());
+                index += 1;
+                if self.token_type_at(index) != TokenType::Comma {
+                    break;
+                }
+                index += 1;
+            }
+        }
+        self.expect_token(index, TokenType::Rparen, "')' to close the parameters");
+        let (body, adv) = self.parse_block(index + 1);
+        index += 1 + adv;
+
+        let function = Stmt::Function {
+            name: name.literal.clone(),
+            parameters,
+            body,
+            span: start.span.to(&self.tokens[index - 1].span),
+        };
+        (function, index - from)
     }
 
-    fn right(&self) -> Option<&BoxExpression> {
-        Some(&self.value)
+    /// Parses the statements between the braces of a block whose `{` is at `from`.
+    fn parse_block(&self, from: usize) -> (Vec<Stmt>, usize) {
+        let open = self.expect_token(from, TokenType::Lbrace, "'{'");
+        let mut statements = Vec::new();
+        let mut index = from + 1;
+        loop {
+            match self.tokens.get(index) {
+                Some(token) if token.token_type == TokenType::Rbrace => break,
+                Some(_) => {
+                    let (statement, adv) = self.parse_statement(index);
+                    if let Some(statement) = statement {
+                        statements.push(statement);
+                    }
+                    index += adv;
+                }
+                None => panic!("{}: unclosed '{{'", open.span),
+            }
+        }
+        (statements, index + 1 - from)
     }
-}
 
-pub struct Parser {
-    tokens: Vec<Token>,
-    expressions: Vec<BoxExpression>,
-    token_index: usize,
-}
-
-impl Parser {
-    fn new(tokens: Vec<Token>) -> Parser {
-        Parser {
-            tokens,
-            expressions: Vec::new(),
-            token_index: 0,
+    /// Parses an expression starting at `from`, taking in the infix operators
+    /// that follow as long as their left binding power is at least
+    /// `min_power`. Operators binding looser are left to the caller.
+    fn parse_expression_with_power(&self, from: usize, min_power: u8) -> (Option<Expr>, usize) {
+        let (mut left, mut index) = match self.parse_prefix(from) {
+            (Some(expression), adv) => (expression, from + adv),
+            nothing => return nothing,
+        };
+        while let Some((left_power, right_power)) = infix_binding_power(self.token_type_at(index)) {
+            if left_power < min_power {
+                break;
+            }
+            let (expression, adv) = self.parse_infix_expression(left, index, right_power);
+            left = expression;
+            index += adv;
         }
+        (Some(left), index - from)
     }
 
-    pub fn parse(&mut self) {
-        while self.token_index < self.tokens.len() {
-            let (expression, advance) = self.parse_expression(self.token_index);
-            if let Some(exp) = expression {
-                self.expressions.push(exp);
+    /// Parses the pieces of an interpolated string, starting at its `StringHead`.
+    fn parse_interpolated_string(&self, from: usize) -> (Option<Expr>, usize) {
+        let string_part = |token: &Token| Expr::String {
+            value: token.to_unescaped(),
+            span: token.span.clone(),
+        };
+
+        let mut parts = vec![string_part(&self.tokens[from])];
+        let mut index = from + 1;
+        loop {
+            if matches!(self.token_type_at(index), TokenType::StringMiddle | TokenType::StringTail) {
+                // The piece before ends with the interpolation's '{'.
+                let before = &self.tokens[index - 1];
+                let open = before.span.within(&before.literal, before.literal.len() - 1..before.literal.len());
+                panic!("{}: empty interpolation", open);
+            }
+            let (expression, adv) = self.parse_operand(index, 0);
+            parts.push(expression);
+            index += adv;
+
+            let token = &self.tokens[index];
+            index += 1;
+            match token.token_type {
+                TokenType::StringMiddle => parts.push(string_part(token)),
+                TokenType::StringTail => {
+                    parts.push(string_part(token));
+                    break;
+                }
+                _ => panic!("{}: expected '}}' to close the interpolation, found '{}'", token.span, token.literal),
             }
-            self.token_index = self.token_index + advance;
         }
+
+        let expression = Expr::Interpolation {
+            span: self.tokens[from].span.to(parts.last().unwrap().span()),
+            parts,
+        };
+        (Some(expression), index - from)
     }
 
-    fn peek_next(&self, from: usize) -> Option<Token> {
-        if (from + 1) < self.tokens.len() {
-            Some(self.tokens[from + 1].clone())
-        } else {
-            None
+    /// Parses a call whose function name is at `from`, followed by its
+    /// comma-separated arguments in parentheses.
+    fn parse_call(&self, from: usize) -> (Expr, usize) {
+        let name = &self.tokens[from];
+        let open = &self.tokens[from + 1];
+        let mut arguments = Vec::new();
+        let mut index = from + 2;
+        if self.token_type_at(index) != TokenType::Rparen {
+            loop {
+                let (argument, adv) = self.parse_operand(index, 0);
+                arguments.push(argument);
+                index += adv;
+                match self.tokens.get(index) {
+                    Some(token) if token.token_type == TokenType::Comma => index += 1,
+                    Some(token) if token.token_type == TokenType::Rparen => break,
+                    Some(token) => panic!("{}: expected ',' or ')' after an argument, found '{}'", token.span, token.literal),
+                    None => panic!("{}: unclosed '('", open.span),
+                }
+            }
         }
+        let call = Expr::Call {
+            name: name.literal.clone(),
+            arguments,
+            span: name.span.to(&self.tokens[index].span),
+        };
+        (call, index + 1 - from)
     }
 
-    fn parse_infix_expression(&self, left: BoxExpression, from: usize) -> (BoxExpression, usize) {
-        let (right, adv) = self.parse_expression(from + 1);
-        let infix = InfixExpression {
-            token: self.tokens[from].clone(),
-            left,
-            right: right.unwrap()
+    /// Parses the expression in parentheses whose `(` is at `from`.
+    fn parse_grouping(&self, from: usize) -> (Expr, usize) {
+        let open = &self.tokens[from];
+        let (expr, adv) = self.parse_operand(from + 1, 0);
+        let close = match self.tokens.get(from + 1 + adv) {
+            Some(token) if token.token_type == TokenType::Rparen => token,
+            Some(token) => {
+                panic!(
+                    "{}: expected ')' to close the '(' at {}:{}, found '{}'",
+                    token.span, open.span.line, open.span.column, token.literal
+                )
+            }
+            None => panic!("{}: unclosed '('", open.span),
+        };
+        let grouping = Expr::Grouping {
+            expr: Box::new(expr),
+            span: open.span.to(&close.span),
         };
-        (Box::new(infix), adv + 1)
+        (grouping, adv + 2)
     }
 
-    fn parse_expression(&self, from: usize) -> (Option<BoxExpression>, usize) {
-        let token = &self.tokens[from];
-        match token.token_type {
-            TokenType::Bang => {
-                let (rhs, adv) = self.parse_expression(from + 1);
-                let expression = PrefixExpression {
-                    token: self.tokens[from].clone(),
-                    right: rhs.unwrap(),
-                };
-                return (Some(Box::new(expression)), 1 + adv);
+    /// Parses a literal, identifier or prefix operation, which can then be
+    /// the left operand of an infix operator.
+    fn parse_prefix(&self, from: usize) -> (Option<Expr>, usize) {
+        let token = match self.tokens.get(from) {
+            Some(token) => token,
+            None => return (None, 0),
+        };
+        let span = token.span.clone();
+        let expression = match token.token_type {
+            // 2^63 only fits once negated, so the minus is folded into it.
+            TokenType::Minus if self.is_integer_past_i64_max(from + 1) => {
+                let literal = &self.tokens[from + 1];
+                let value = literal.to_negated_numeric();
+                return (Some(Expr::Number { value, span: span.to(&literal.span) }), 2);
             }
-            TokenType::String => {
-                let expression = StringLiteralExpression {
-                    token: self.tokens[from].clone(),
-                    value: self.tokens[from].literal.clone(),
-                };
-                return (Some(Box::new(expression)), 1);
-            },
-            TokenType::Number => {
-                let expression = NumberLiteralExpression {
-                    token: self.tokens[from].clone(),
-                    value: self.tokens[from].to_numeric(),
+            TokenType::Bang | TokenType::Minus | TokenType::Tilde => {
+                let (right, adv) = self.parse_operand(from + 1, PREFIX_BINDING_POWER);
+                let expression = Expr::Prefix {
+                    operator: token.token_type,
+                    span: span.to(right.span()),
+                    right: Box::new(right),
                 };
-                return (Some(Box::new(expression)), 1);
+                return (Some(expression), 1 + adv);
             }
-            TokenType::Identifier => todo!(),
-            TokenType::Assignment => todo!(),
-            TokenType::Plus | TokenType::Minus => {
-                let (lhs, _) = self.parse_expression(from - 1);
-                let (rhs, adv) = self.parse_expression(from + 1);
-
-                let expression = InfixExpression {
-                    token: self.tokens[from].clone(),
-                    left: lhs.unwrap(),
-                    right: rhs.unwrap(),
-                };
-                return (Some(Box::new(expression)), 1 + adv);
+            TokenType::String | TokenType::RawString => Expr::String { value: token.to_unescaped(), span },
+            TokenType::Number => Expr::Number { value: token.to_numeric(), span },
+            TokenType::StringHead => return self.parse_interpolated_string(from),
+            TokenType::StringMiddle | TokenType::StringTail => {
+                panic!("{}: unexpected '{}' outside of a string", token.span, token.literal)
+            }
+            TokenType::Float => Expr::Float { value: token.to_float(), span },
+            TokenType::Char => Expr::Char { value: token.to_char(), span },
+            TokenType::KeywordTrue | TokenType::KeywordFalse => {
+                Expr::Boolean { value: token.token_type == TokenType::KeywordTrue, span }
+            }
+            TokenType::Identifier if self.token_type_at(from + 1) == TokenType::Lparen => {
+                let (call, adv) = self.parse_call(from);
+                return (Some(call), adv);
             }
-            TokenType::Asterisk => todo!(),
+            TokenType::Identifier => Expr::Identifier { name: token.literal.clone(), span },
             TokenType::Semicolon => return (None, 1),
-            TokenType::Equals => todo!(),
-            TokenType::Unequal => todo!(),
-            TokenType::Lt => todo!(),
-            TokenType::Gt => todo!(),
-            TokenType::Lteq => todo!(),
-            TokenType::Gteq => todo!(),
-            TokenType::Lparen => todo!(),
-            TokenType::Rparen => todo!(),
-            TokenType::Lbrace => todo!(),
-            TokenType::Rbrace => todo!(),
-            TokenType::KeywordIf => todo!(),
-            TokenType::KeywordPrint => todo!(),
-            TokenType::KeywordReturn => {
-                let (right_expression, adv) = self.parse_expression(self.token_index + 1);
-                let expression = ReturnExpression {
-                    token: self.tokens[from].clone(),
-                    value: right_expression.unwrap(),
-                };
-                return (Some(Box::new(expression)), 1 + adv);
+            TokenType::Assignment
+            | TokenType::Plus | TokenType::Asterisk | TokenType::Slash | TokenType::Percent
+            | TokenType::Equals | TokenType::Unequal
+            | TokenType::Lt | TokenType::Gt | TokenType::Lteq | TokenType::Gteq
+            | TokenType::And | TokenType::Or | TokenType::Ampersand | TokenType::Pipe | TokenType::Caret
+            | TokenType::Lshift | TokenType::Rshift
+            | TokenType::KeywordPrint | TokenType::KeywordReturn | TokenType::KeywordLet | TokenType::KeywordFn
+            | TokenType::KeywordWhile => {
+                panic!("{}: expected an expression, found '{}'", token.span, token.literal)
             }
-            TokenType::KeywordWhile => todo!(),
-            TokenType::KeywordLet => todo!(),
-            TokenType::KeywordFn => todo!(),
-            TokenType::None => ((None), 0),
-        }
+            TokenType::Lparen => {
+                let (grouping, adv) = self.parse_grouping(from);
+                return (Some(grouping), adv);
+            }
+            TokenType::Rparen | TokenType::Rbrace | TokenType::Comma => return (None, 0),
+            TokenType::Lbrace => panic!("{}: unexpected '{{'", token.span),
+            TokenType::KeywordIf => {
+                panic!("{}: '{}' is not supported yet", token.span, token.literal)
+            }
+            TokenType::KeywordElse
+            | TokenType::KeywordFor | TokenType::KeywordIn | TokenType::KeywordBreak | TokenType::KeywordContinue
+            | TokenType::KeywordConst | TokenType::KeywordImport | TokenType::KeywordExtern | TokenType::KeywordStruct => {
+                panic!("{}: '{}' is a reserved keyword", token.span, token.literal)
+            }
+            TokenType::Eof => return (None, 1),
+            TokenType::None => return (None, 0),
+        };
+        (Some(expression), 1)
     }
 }
 
-pub fn parse(tokens: Vec<Token>) -> Vec<BoxExpression> {
+pub fn parse(tokens: Vec<Token>) -> Vec<Stmt> {
     let mut parser = Parser::new(tokens);
     parser.parse();
-    parser.expressions
+    parser.statements
 }
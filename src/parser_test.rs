@@ -1,226 +1,862 @@
 use super::*;
-use crate::{tokens::TokenType, parser::ExpressionType};
+use crate::{tokens::{Span, TokenType}, parser::{Expr, Stmt}};
 use lexer::lex_program;
 use parser::parse;
 
+/// Checks that `$seen` is a prefix operation and gives its operand.
 macro_rules! expect_prefix {
-    ($toktype:expr, $seen:expr) => {
-        assert_eq!(ExpressionType::PrefixExpression, $seen.get_type(), "expected a prefix expression");
-        assert_eq!($toktype, $seen.token.token_type);
-    }
+    ($operator:expr, $seen:expr) => {
+        match $seen {
+            Expr::Prefix { operator, right, .. } => {
+                assert_eq!($operator, *operator, "wrong prefix operator");
+                &**right
+            }
+            other => panic!("expected a prefix expression, found {:?}", other),
+        }
+    };
 }
 
+/// Checks that `$seen` is an infix operation and gives its two operands.
 macro_rules! expect_infix {
-    ($toktype:expr, $seen:expr) => {
-        assert_eq!(ExpressionType::InfixExpression, $seen.get_type(), "expected an infix expression");
-        assert_eq!($toktype, $seen.token.token_type);
+    ($operator:expr, $seen:expr) => {
+        match $seen {
+            Expr::Infix { operator, left, right, .. } => {
+                assert_eq!($operator, *operator, "wrong infix operator");
+                (&**left, &**right)
+            }
+            other => panic!("expected an infix expression, found {:?}", other),
+        }
     };
 }
 
 macro_rules! expect_number {
     ($number:expr, $seen:expr) => {
-        assert_eq!(ExpressionType::Number, $seen.get_type(), "expected a number");
-        assert_eq!(format!("{}", $number), *$seen.as_str(), "wrong number value");
-    }
+        match $seen {
+            Expr::Number { value, .. } => assert_eq!($number, *value, "wrong number value"),
+            other => panic!("expected a number, found {:?}", other),
+        }
+    };
 }
 
 macro_rules! expect_identifier {
-    ($lit:expr, $seen:expr) => {
-        assert_eq!(ExpressionType::Identifier, $seen.get_type(), "expected an infix expression");
-        assert_eq!($lit, $seen.to_string(), "wrong literal value");
-    }
+    ($name:expr, $seen:expr) => {
+        match $seen {
+            Expr::Identifier { name, .. } => assert_eq!($name, *name, "wrong identifier"),
+            other => panic!("expected an identifier, found {:?}", other),
+        }
+    };
 }
 
 macro_rules! expect_string {
-    ($str:expr, $seen:expr) => {
-        assert_eq!(ExpressionType::String, $seen.get_type(), "expected a string");
-        assert_eq!($str, $seen.to_string(), "wrong string value");
-    }
+    ($string:expr, $seen:expr) => {
+        match $seen {
+            Expr::String { value, .. } => assert_eq!($string, value, "wrong string value"),
+            other => panic!("expected a string, found {:?}", other),
+        }
+    };
 }
 
+/// Checks that `$seen` is an expression statement and gives its expression.
+macro_rules! expect_expression {
+    ($seen:expr) => {
+        match $seen {
+            Stmt::Expression { expr } => expr,
+            other => panic!("expected an expression statement, found {:?}", other),
+        }
+    };
+}
+
+/// Checks that `$seen` is a return statement and gives its value.
 macro_rules! expect_return {
-    ($a:expr) => {
-        assert_eq!(ExpressionType::ReturnExpression, $a.expression_type);
-        assert_eq!(TokenType::KeywordReturn, $a.token.token_type, "return token is not 'return'");
+    ($seen:expr) => {
+        match $seen {
+            Stmt::Return { value, .. } => value,
+            other => panic!("expected a return statement, found {:?}", other),
+        }
+    };
+}
+
+/// Gives `expression` with every span reset, so it can be compared with a
+/// tree built by hand.
+fn without_spans(expression: Expr) -> Expr {
+    let span = Span::default();
+    match expression {
+        Expr::Identifier { name, .. } => Expr::Identifier { name, span },
+        Expr::Number { value, .. } => Expr::Number { value, span },
+        Expr::Float { value, .. } => Expr::Float { value, span },
+        Expr::String { value, .. } => Expr::String { value, span },
+        Expr::Boolean { value, .. } => Expr::Boolean { value, span },
+        Expr::Char { value, .. } => Expr::Char { value, span },
+        Expr::Interpolation { parts, .. } => {
+            Expr::Interpolation { parts: parts.into_iter().map(without_spans).collect(), span }
+        }
+        Expr::Call { name, arguments, .. } => {
+            Expr::Call { name, arguments: arguments.into_iter().map(without_spans).collect(), span }
+        }
+        Expr::Grouping { expr, .. } => Expr::Grouping { expr: Box::new(without_spans(*expr)), span },
+        Expr::Prefix { operator, right, .. } => {
+            Expr::Prefix { operator, right: Box::new(without_spans(*right)), span }
+        }
+        Expr::Infix { operator, left, right, .. } => {
+            Expr::Infix { operator, left: Box::new(without_spans(*left)), right: Box::new(without_spans(*right)), span }
+        }
     }
 }
 
+fn statement_without_spans(statement: Stmt) -> Stmt {
+    let span = Span::default();
+    match statement {
+        Stmt::Expression { expr } => Stmt::Expression { expr: without_spans(expr) },
+        Stmt::Let { name, value, .. } => Stmt::Let { name, value: without_spans(value), span },
+        Stmt::Assign { name, value, .. } => Stmt::Assign { name, value: without_spans(value), span },
+        Stmt::Return { value, .. } => Stmt::Return { value: without_spans(value), span },
+        Stmt::Print { value, .. } => Stmt::Print { value: without_spans(value), span },
+        Stmt::Function { name, parameters, body, .. } => {
+            Stmt::Function { name, parameters, body: body.into_iter().map(statement_without_spans).collect(), span }
+        }
+        Stmt::While { condition, body, .. } => Stmt::While {
+            condition: without_spans(condition),
+            body: body.into_iter().map(statement_without_spans).collect(),
+            span,
+        },
+    }
+}
+
+/// Parses `program` into statements without spans.
+fn parse_statements(program: &str) -> Vec<Stmt> {
+    parse(lex_program(program).unwrap()).into_iter().map(statement_without_spans).collect()
+}
+
+/// Parses a program made of a single expression statement.
+fn parse_expression(program: &str) -> Expr {
+    let mut statements = parse_statements(program);
+    assert_eq!(1, statements.len(), "number of statements");
+    match statements.remove(0) {
+        Stmt::Expression { expr } => expr,
+        other => panic!("expected an expression statement, found {:?}", other),
+    }
+}
+
+fn number(value: i64) -> Expr {
+    Expr::Number { value, span: Span::default() }
+}
+
+fn identifier(name: &str) -> Expr {
+    Expr::Identifier { name: name.into(), span: Span::default() }
+}
+
+fn string(value: &str) -> Expr {
+    Expr::String { value: value.to_string(), span: Span::default() }
+}
+
+fn boolean(value: bool) -> Expr {
+    Expr::Boolean { value, span: Span::default() }
+}
+
+fn grouping(expr: Expr) -> Expr {
+    Expr::Grouping { expr: Box::new(expr), span: Span::default() }
+}
+
+fn prefix(operator: TokenType, right: Expr) -> Expr {
+    Expr::Prefix { operator, right: Box::new(right), span: Span::default() }
+}
+
+fn infix(operator: TokenType, left: Expr, right: Expr) -> Expr {
+    Expr::Infix { operator, left: Box::new(left), right: Box::new(right), span: Span::default() }
+}
+
 #[test]
 fn literals() {
-    let tokens = lex_program(r#"1337 "banana" tomato;"#);
-    let expressions = parse(tokens);
+    let tokens = lex_program(r#"1337 "banana" tomato;"#).unwrap();
+    let statements = parse(tokens);
 
-    assert_eq!(3, expressions.len());
-    expect_number!(1337, expressions[0]);
-    //expect_string!("banana", expressions[1]);
-    //expect_identifier!("tomato", expressions[2]);
+    assert_eq!(3, statements.len());
+    expect_number!(1337, expect_expression!(&statements[0]));
+    expect_string!("banana", expect_expression!(&statements[1]));
+    expect_identifier!("tomato", expect_expression!(&statements[2]));
 }
 
-/*#[test]
-fn unary_negation() {
-    let tokens = lex_program("!5;");
-    let expressions = parse(tokens);
+#[test]
+fn string_literal_value_is_decoded() {
+    assert_eq!(string("a\tb\"c\u{e9}"), parse_expression(r#""a\tb\"c\u{e9}";"#));
+}
 
-    assert_eq!(1, expressions.len(), "number of expressions");
-    expect_prefix!(TokenType::Bang, expressions[0]);
+#[test]
+fn raw_string_literal_value_is_kept_as_is() {
+    assert_eq!(string("a\\t{b}\n"), parse_expression("r#\"a\\t{b}\n\"#;"));
+}
 
-    let right_expression = expressions[0].right_side();
-    expect_number!(5, right_expression);
+#[test]
+fn boolean_literals() {
+    assert_eq!(
+        vec![
+            Stmt::Expression { expr: boolean(true) },
+            Stmt::Expression { expr: prefix(TokenType::Bang, boolean(false)) },
+        ],
+        parse_statements("true; !false;")
+    );
 }
 
 #[test]
-fn double_negation() {
-    let tokens = lex_program("!!5;");
-    let expressions = parse(tokens);
+fn float_literals() {
+    assert_eq!(Expr::Float { value: 0.0025, span: Span::default() }, parse_expression("2.5e-3;"));
+}
 
-    assert_eq!(1, expressions.len(), "number of expressions");
-    expect_prefix!(TokenType::Bang, expressions[0]);
+#[test]
+fn char_literals() {
+    assert_eq!(
+        vec![
+            Stmt::Expression { expr: Expr::Char { value: 'a', span: Span::default() } },
+            Stmt::Expression { expr: Expr::Char { value: '\n', span: Span::default() } },
+        ],
+        parse_statements(r"'a'; '\n';")
+    );
+}
 
-    let right_expression = expressions[0].right_side();
-    expect_prefix!(TokenType::Bang, right_expression);
+#[test]
+fn spans_cover_operands() {
+    let tokens = lex_program("x;\nreturn !!5;").unwrap();
+    let statements = parse(tokens);
 
-    let numeric_expression = right_expression.right_side();
-    expect_number!(5, numeric_expression);
+    assert_eq!(2, statements.len());
+    let span = statements[1].span();
+    assert_eq!((3, 10, 2, 1), (span.offset, span.len, span.line, span.column));
+
+    let not_not_five = expect_return!(&statements[1]);
+    expect_prefix!(TokenType::Bang, not_not_five);
+    let span = not_not_five.span();
+    assert_eq!((10, 3, 2, 8), (span.offset, span.len, span.line, span.column));
 }
 
 #[test]
-fn return_number() {
-    let tokens = lex_program("return 42;");
-    let expressions = parse(tokens);
+fn interpolated_string() {
+    let tokens = lex_program(r#""x is {x}{2}, ok\n";"#).unwrap();
+    let statements = parse(tokens);
+
+    assert_eq!(1, statements.len());
+    let span = statements[0].span();
+    assert_eq!((0, 19), (span.offset, span.len));
+
+    let parts = vec![string("x is "), identifier("x"), string(""), number(2), string(", ok\n")];
+    assert_eq!(
+        Expr::Interpolation { parts, span: Span::default() },
+        parse_expression(r#""x is {x}{2}, ok\n";"#)
+    );
+}
 
-    assert_eq!(1, expressions.len(), "number of expressions");
-    expect_return!(expressions[0]);
+#[test]
+fn print_statement() {
+    let interpolation = Expr::Interpolation { parts: vec![string("hi "), boolean(true), string("")], span: Span::default() };
+    assert_eq!(
+        vec![
+            Stmt::Print { value: interpolation, span: Span::default() },
+            Stmt::Print { value: number(3), span: Span::default() },
+        ],
+        parse_statements(r#"print("hi {true}"); print(3);"#)
+    );
+}
 
-    let number_42 = expressions[0].right_side();
-    expect_number!(42, number_42);
+#[test]
+fn multiplication_binds_tighter_than_addition() {
+    assert_eq!(
+        infix(TokenType::Plus, infix(TokenType::Asterisk, number(2), number(3)), number(4)),
+        parse_expression("2 * 3 + 4;")
+    );
+    assert_eq!(
+        infix(TokenType::Plus, number(2), infix(TokenType::Asterisk, number(3), number(4))),
+        parse_expression("2 + 3 * 4;")
+    );
+    assert_eq!(
+        infix(
+            TokenType::Minus,
+            infix(TokenType::Plus, number(1), infix(TokenType::Percent, infix(TokenType::Slash, number(2), number(3)), number(4))),
+            number(5)
+        ),
+        parse_expression("1 + 2 / 3 % 4 - 5;")
+    );
 }
 
 #[test]
-fn return_expression() {
-    let tokens = lex_program("return !1;");
-    let expressions = parse(tokens);
+fn infix_operators_are_left_associative() {
+    assert_eq!(
+        infix(TokenType::Minus, infix(TokenType::Minus, identifier("a"), identifier("b")), identifier("c")),
+        parse_expression("a - b - c;")
+    );
+    assert_eq!(
+        infix(TokenType::Slash, infix(TokenType::Slash, identifier("a"), identifier("b")), identifier("c")),
+        parse_expression("a / b / c;")
+    );
+    assert_eq!(
+        infix(TokenType::Rshift, infix(TokenType::Lshift, identifier("a"), identifier("b")), identifier("c")),
+        parse_expression("a << b >> c;")
+    );
+}
+
+#[test]
+fn prefix_operators_bind_tightest() {
+    assert_eq!(
+        infix(TokenType::Equals, prefix(TokenType::Bang, identifier("x")), identifier("y")),
+        parse_expression("!x == y;")
+    );
+    assert_eq!(
+        infix(TokenType::Plus, prefix(TokenType::Minus, identifier("a")), prefix(TokenType::Tilde, identifier("b"))),
+        parse_expression("-a + ~b;")
+    );
+    assert_eq!(
+        infix(TokenType::Minus, identifier("a"), prefix(TokenType::Minus, identifier("b"))),
+        parse_expression("a - -b;")
+    );
+}
 
-    assert_eq!(1, expressions.len(), "number of expressions");
-    expect_return!(expressions[0]);
-    let not_one = expressions[0].right_side();
-    expect_prefix!(TokenType::Bang, not_one);
-    let number_one = not_one.right_side();
-    expect_number!(1, number_one);
+#[test]
+fn unary_minus_and_complement() {
+    assert_eq!(prefix(TokenType::Minus, number(5)), parse_expression("-5;"));
+    assert_eq!(
+        prefix(TokenType::Minus, grouping(infix(TokenType::Plus, identifier("a"), identifier("b")))),
+        parse_expression("-(a + b);")
+    );
+    assert_eq!(
+        infix(TokenType::Asterisk, prefix(TokenType::Tilde, identifier("x")), prefix(TokenType::Minus, number(2))),
+        parse_expression("~x * -2;")
+    );
 }
 
 #[test]
-fn return_negation_of_negation() {
-    let tokens = lex_program("return !!5;");
-    let expressions = parse(tokens);
+fn smallest_integer_literal() {
+    assert_eq!(number(i64::MIN), parse_expression("-9223372036854775808;"));
+    assert_eq!(number(i64::MIN), parse_expression("-0x8000_0000_0000_0000;"));
+    assert_eq!(
+        infix(TokenType::Asterisk, number(i64::MIN), number(1)),
+        parse_expression("-9223372036854775808 * 1;")
+    );
+}
 
-    assert_eq!(1, expressions.len(), "number of expressions");
-    expect_return!(expressions[0]);
-    let not_not_five = expressions[0].right_side();
-    expect_prefix!(TokenType::Bang, not_not_five);
-    let not_five = not_not_five.right_side();
-    expect_prefix!(TokenType::Bang, not_five);
-    let five = not_five.right_side();
+#[test]
+#[should_panic(expected = "1:5: integer literal '9223372036854775808' does not fit in 64 bits")]
+fn smallest_integer_magnitude_needs_a_minus() {
+    parse_expression("1 - 9223372036854775808;");
+}
+
+#[test]
+fn binding_power_table() {
+    // From loosest to tightest.
+    let operators = [
+        TokenType::Or, TokenType::And, TokenType::Equals, TokenType::Lt, TokenType::Pipe,
+        TokenType::Caret, TokenType::Ampersand, TokenType::Lshift, TokenType::Plus, TokenType::Asterisk,
+    ];
+    let names = ["a", "b", "c", "d", "e", "f", "g", "h", "i", "j", "k"];
+
+    let loosest_first = operators.iter().zip(&names).rev()
+        .fold(identifier("k"), |right, (operator, name)| infix(*operator, identifier(name), right));
+    assert_eq!(loosest_first, parse_expression("a || b && c == d < e | f ^ g & h << i + j * k;"));
+
+    let tightest_first = operators.iter().rev().zip(&names[1..])
+        .fold(identifier("a"), |left, (operator, name)| infix(*operator, left, identifier(name)));
+    assert_eq!(tightest_first, parse_expression("a * b + c << d & e ^ f | g < h == i && j || k;"));
+
+    assert_eq!(
+        infix(
+            TokenType::Unequal,
+            infix(TokenType::Gteq, identifier("a"), identifier("b")),
+            infix(TokenType::Lteq, identifier("c"), identifier("d"))
+        ),
+        parse_expression("a >= b != c <= d;")
+    );
+}
+
+#[test]
+fn comparisons_bind_looser_than_arithmetic() {
+    assert_eq!(
+        infix(TokenType::Gt, identifier("y"), number(12)),
+        parse_expression("y > 12;")
+    );
+    assert_eq!(
+        infix(
+            TokenType::Equals,
+            infix(
+                TokenType::Lt,
+                infix(TokenType::Plus, identifier("a"), number(1)),
+                infix(TokenType::Asterisk, identifier("b"), number(2))
+            ),
+            identifier("c")
+        ),
+        parse_expression("a + 1 < b * 2 == c;")
+    );
+    assert_eq!(
+        infix(TokenType::Lteq, infix(TokenType::Lteq, identifier("a"), identifier("b")), identifier("c")),
+        parse_expression("a <= b <= c;")
+    );
+}
+
+#[test]
+fn grouping_overrides_binding_power() {
+    assert_eq!(
+        infix(TokenType::Asterisk, grouping(infix(TokenType::Plus, identifier("a"), identifier("b"))), identifier("c")),
+        parse_expression("(a + b) * c;")
+    );
+    assert_eq!(
+        infix(TokenType::Minus, identifier("a"), grouping(infix(TokenType::Minus, identifier("b"), identifier("c")))),
+        parse_expression("a - (b - c);")
+    );
+    assert_eq!(
+        prefix(TokenType::Bang, grouping(infix(TokenType::Equals, identifier("x"), identifier("y")))),
+        parse_expression("!(x == y);")
+    );
+}
+
+#[test]
+fn nested_groupings() {
+    assert_eq!(grouping(grouping(number(1))), parse_expression("((1));"));
+    assert_eq!(
+        grouping(infix(TokenType::Asterisk, number(2), grouping(infix(TokenType::Plus, number(3), number(4))))),
+        parse_expression("(2 * (3 + 4));")
+    );
+}
+
+#[test]
+fn grouping_span_covers_parentheses() {
+    let tokens = lex_program("(1 + 2) * 3;").unwrap();
+    let statements = parse(tokens);
+
+    let (group, _) = expect_infix!(TokenType::Asterisk, expect_expression!(&statements[0]));
+    let span = group.span();
+    assert_eq!((0, 7), (span.offset, span.len));
+    let span = statements[0].span();
+    assert_eq!((0, 11), (span.offset, span.len));
+}
+
+#[test]
+#[should_panic(expected = "1:7: expected ')' to close the '(' at 1:1, found ';'")]
+fn missing_closing_parenthesis() {
+    parse(lex_program("(1 + 2;").unwrap());
+}
+
+#[test]
+#[should_panic(expected = "1:1: unclosed '('")]
+fn unclosed_parenthesis_at_end_of_input() {
+    parse(lex_program("(1 + 2").unwrap());
+}
+
+#[test]
+#[should_panic(expected = "1:6: unmatched ')'")]
+fn unmatched_closing_parenthesis() {
+    parse(lex_program("1 + 2);").unwrap());
+}
+
+#[test]
+#[should_panic(expected = "1:2: expected an expression, found ')'")]
+fn empty_parentheses() {
+    parse(lex_program("();").unwrap());
+}
+
+#[test]
+#[should_panic(expected = "1:5: expected an expression, found ';'")]
+fn missing_right_operand() {
+    parse(lex_program("1 + ;").unwrap());
+}
+
+#[test]
+#[should_panic(expected = "1:1: expected an expression, found '*'")]
+fn infix_operator_without_left_operand() {
+    parse(lex_program("* 2;").unwrap());
+}
+
+#[test]
+#[should_panic(expected = "1:5: expected an expression, found 'return'")]
+fn return_is_not_an_operand() {
+    parse(lex_program("1 + return 2;").unwrap());
+}
+
+#[test]
+fn unary_negation() {
+    let tokens = lex_program("!5;").unwrap();
+    let statements = parse(tokens);
+
+    assert_eq!(1, statements.len(), "number of statements");
+    let five = expect_prefix!(TokenType::Bang, expect_expression!(&statements[0]));
     expect_number!(5, five);
 }
 
 #[test]
-fn addition_of_two_numbers() {
-    let tokens = lex_program("12 + 4;");
-    let expressions = parse(tokens);
+fn double_negation() {
+    let tokens = lex_program("!!5;").unwrap();
+    let statements = parse(tokens);
 
-    assert_eq!(1, expressions.len(), "number of expressions");
-    expect_infix!(TokenType::Plus, expressions[0]);
-    let left_expression = expressions[0].left_side();
-    let right_expression = expressions[0].right_side();
-    expect_number!(12, left_expression);
-    expect_number!(4, right_expression);
+    assert_eq!(1, statements.len(), "number of statements");
+    let not_five = expect_prefix!(TokenType::Bang, expect_expression!(&statements[0]));
+    let five = expect_prefix!(TokenType::Bang, not_five);
+    expect_number!(5, five);
 }
 
 #[test]
-fn return_addition_of_two_numbers() {
-    let tokens = lex_program("return 12 + 4;");
-    let expressions = parse(tokens);
+fn return_number() {
+    let tokens = lex_program("return 42;").unwrap();
+    let statements = parse(tokens);
 
-    assert_eq!(1, expressions.len(), "number of expressions");
-    expect_return!(expressions[0]);
+    assert_eq!(1, statements.len(), "number of statements");
+    expect_number!(42, expect_return!(&statements[0]));
+}
 
-    let addition = expressions[0].right_side();
-    expect_infix!(TokenType::Plus, addition);
+#[test]
+fn return_expression() {
+    let tokens = lex_program("return !1;").unwrap();
+    let statements = parse(tokens);
 
-    let left_expression = addition.left_side();
-    let right_expression = addition.right_side();
-    expect_number!(12, left_expression);
-    expect_number!(4, right_expression);
+    assert_eq!(1, statements.len(), "number of statements");
+    let not_one = expect_return!(&statements[0]);
+    expect_number!(1, expect_prefix!(TokenType::Bang, not_one));
 }
 
 #[test]
-fn return_addition_of_three_numbers() {
-    let tokens = lex_program("return 12 + 4 + 6;");
-    let expressions = parse(tokens);
+fn return_negation_of_negation() {
+    assert_eq!(
+        vec![Stmt::Return { value: prefix(TokenType::Bang, prefix(TokenType::Bang, number(5))), span: Span::default() }],
+        parse_statements("return !!5;")
+    );
+}
+
+#[test]
+fn addition_of_two_numbers() {
+    let tokens = lex_program("12 + 4;").unwrap();
+    let statements = parse(tokens);
 
-    assert_eq!(1, expressions.len(), "number of expressions");
-    expect_return!(expressions[0]);
+    assert_eq!(1, statements.len(), "number of statements");
+    let (left, right) = expect_infix!(TokenType::Plus, expect_expression!(&statements[0]));
+    expect_number!(12, left);
+    expect_number!(4, right);
+}
 
-    let addition_12_rest = expressions[0].right_side();
-    expect_infix!(TokenType::Plus, addition_12_rest);
-    expect_number!(12, addition_12_rest.left_side());
+#[test]
+fn return_addition_of_two_numbers() {
+    let tokens = lex_program("return 12 + 4;").unwrap();
+    let statements = parse(tokens);
 
-    let addition_4_6 = addition_12_rest.right_side();
-    expect_infix!(TokenType::Plus, addition_4_6);
-    expect_number!(4, addition_4_6.left_side());
-    expect_number!(6, addition_4_6.right_side());
+    assert_eq!(1, statements.len(), "number of statements");
+    let (left, right) = expect_infix!(TokenType::Plus, expect_return!(&statements[0]));
+    expect_number!(12, left);
+    expect_number!(4, right);
 }
 
 #[test]
-fn return_addition_of_four_numbers() {
-    let tokens = lex_program("return 12 + 4 + 6 + 3;");
-    let expressions = parse(tokens);
+fn return_addition_of_three_numbers() {
+    let tokens = lex_program("return 12 + 4 + 6;").unwrap();
+    let statements = parse(tokens);
 
-    assert_eq!(1, expressions.len(), "number of expressions");
-    expect_return!(expressions[0]);
+    assert_eq!(1, statements.len(), "number of statements");
+    let (addition_12_4, six) = expect_infix!(TokenType::Plus, expect_return!(&statements[0]));
+    expect_number!(6, six);
 
-    let addition_12_rest = expressions[0].right_side();
-    expect_number!(12, addition_12_rest.left_side());
+    let (twelve, four) = expect_infix!(TokenType::Plus, addition_12_4);
+    expect_number!(12, twelve);
+    expect_number!(4, four);
+}
 
-    let addition_4_rest = addition_12_rest.right_side();
-    expect_number!(4, addition_4_rest.left_side());
+#[test]
+fn return_addition_of_four_numbers() {
+    let sum = infix(
+        TokenType::Plus,
+        infix(TokenType::Plus, infix(TokenType::Plus, number(12), number(4)), number(6)),
+        number(3),
+    );
+    assert_eq!(
+        vec![Stmt::Return { value: sum, span: Span::default() }],
+        parse_statements("return 12 + 4 + 6 + 3;")
+    );
+}
 
-    let addition_6_3 = addition_4_rest.right_side();
-    expect_number!(6, addition_6_3.left_side());
-    expect_number!(3, addition_6_3.right_side());
+#[test]
+fn return_takes_a_whole_expression() {
+    let value = infix(TokenType::Plus, infix(TokenType::Asterisk, number(2), identifier("x")), number(1));
+    assert_eq!(
+        vec![Stmt::Return { value, span: Span::default() }],
+        parse_statements("return 2 * x + 1;")
+    );
 }
 
 #[test]
 fn subtraction_of_two_numbers() {
-    let tokens = lex_program("12 - 4;");
-    let expressions = parse(tokens);
+    let tokens = lex_program("12 - 4;").unwrap();
+    let statements = parse(tokens);
 
-    assert_eq!(1, expressions.len(), "number of expressions");
-    expect_infix!(TokenType::Minus, expressions[0]);
-    let left_expression = expressions[0].left_side();
-    let right_expression = expressions[0].right_side();
-    expect_number!(12, left_expression);
-    expect_number!(4, right_expression);
+    assert_eq!(1, statements.len(), "number of statements");
+    let (left, right) = expect_infix!(TokenType::Minus, expect_expression!(&statements[0]));
+    expect_number!(12, left);
+    expect_number!(4, right);
 }
 
 #[test]
 fn subtraction_of_four_numbers() {
-    let tokens = lex_program("10 - 2 - 4 - 1;");
-    let expressions = parse(tokens);
+    assert_eq!(
+        infix(
+            TokenType::Minus,
+            infix(TokenType::Minus, infix(TokenType::Minus, number(10), number(2)), number(4)),
+            number(1)
+        ),
+        parse_expression("10 - 2 - 4 - 1;")
+    );
+}
+
+#[test]
+fn let_and_assignment() {
+    assert_eq!(
+        vec![
+            Stmt::Let { name: "x".into(), value: infix(TokenType::Plus, number(1), number(2)), span: Span::default() },
+            Stmt::Assign { name: "x".into(), value: infix(TokenType::Asterisk, identifier("x"), number(3)), span: Span::default() },
+            Stmt::Expression { expr: infix(TokenType::Equals, identifier("x"), number(9)) },
+        ],
+        parse_statements("let x = 1 + 2; x = x * 3; x == 9;")
+    );
+}
+
+#[test]
+fn let_span_covers_the_value() {
+    let tokens = lex_program("let answer = 6 * 7;").unwrap();
+    let statements = parse(tokens);
+
+    let span = statements[0].span();
+    assert_eq!((0, 18), (span.offset, span.len));
+}
+
+#[test]
+#[should_panic(expected = "1:5: expected a variable name after 'let', found '='")]
+fn let_without_a_name() {
+    parse(lex_program("let = 5;").unwrap());
+}
+
+#[test]
+#[should_panic(expected = "1:7: expected '=', found '5'")]
+fn let_without_equals() {
+    parse(lex_program("let x 5;").unwrap());
+}
+
+#[test]
+#[should_panic(expected = "1:7: expected an expression, found '='")]
+fn assignment_to_an_expression() {
+    parse(lex_program("x + 1 = 2;").unwrap());
+}
+
+fn function(name: &str, parameters: &[&str], body: Vec<Stmt>) -> Stmt {
+    let parameters = parameters.iter().map(|&parameter| parameter.into()).collect();
+    Stmt::Function { name: name.into(), parameters, body, span: Span::default() }
+}
+
+#[test]
+fn function_declaration() {
+    assert_eq!(
+        vec![function(
+            "add",
+            &["a", "b"],
+            vec![
+                Stmt::Let { name: "sum".into(), value: infix(TokenType::Plus, identifier("a"), identifier("b")), span: Span::default() },
+                Stmt::Return { value: identifier("sum"), span: Span::default() },
+            ],
+        )],
+        parse_statements("fn add(a, b) { let sum = a + b; return sum; }")
+    );
+}
+
+#[test]
+fn bare_function_declarations() {
+    assert_eq!(
+        vec![
+            function("square", &["x"], vec![
+                Stmt::Return { value: infix(TokenType::Asterisk, identifier("x"), identifier("x")), span: Span::default() },
+            ]),
+            function("main", &[], vec![]),
+            Stmt::Expression { expr: identifier("main") },
+        ],
+        parse_statements("square(x) {\n    return x * x;\n}\n\nmain() {}\nmain;")
+    );
+}
+
+#[test]
+fn function_span_covers_the_body() {
+    let tokens = lex_program("x;\nfn f() {\n  return 1;\n}").unwrap();
+    let statements = parse(tokens);
+
+    let span = statements[1].span();
+    assert_eq!((3, 22, 2, 1), (span.offset, span.len, span.line, span.column));
+}
+
+#[test]
+#[should_panic(expected = "1:4: expected a function name, found '('")]
+fn function_without_a_name() {
+    parse(lex_program("fn (x) {}").unwrap());
+}
+
+#[test]
+#[should_panic(expected = "1:8: expected ')' to close the parameters, found 'b'")]
+fn parameters_without_commas() {
+    parse(lex_program("fn f(a b) {}").unwrap());
+}
+
+#[test]
+#[should_panic(expected = "1:12: duplicate parameter 'a'")]
+fn duplicate_parameters() {
+    parse(lex_program("fn f(a, b, a) {}").unwrap());
+}
+
+#[test]
+#[should_panic(expected = "1:8: unclosed '{'")]
+fn unclosed_function_body() {
+    parse(lex_program("fn f() { return 1;").unwrap());
+}
+
+#[test]
+#[should_panic(expected = "1:4: unmatched '}'")]
+fn unmatched_closing_brace() {
+    parse(lex_program("1; }").unwrap());
+}
+
+#[test]
+fn while_loop() {
+    assert_eq!(
+        vec![Stmt::While {
+            condition: infix(TokenType::Gt, identifier("y"), number(12)),
+            body: vec![Stmt::Assign {
+                name: "y".into(),
+                value: infix(TokenType::Minus, identifier("y"), number(1)),
+                span: Span::default(),
+            }],
+            span: Span::default(),
+        }],
+        parse_statements("while y > 12 { y = y - 1; }")
+    );
+}
 
-    assert_eq!(1, expressions.len(), "number of expressions");
+#[test]
+fn while_span_covers_the_body() {
+    let tokens = lex_program("while 1 {
+  f();
+}").unwrap();
+    let statements = parse(tokens);
+
+    let span = statements[0].span();
+    assert_eq!((0, 18, 1, 1), (span.offset, span.len, span.line, span.column));
+}
 
-    let subtraction_10_rest = &expressions[0];
-    expect_infix!(TokenType::Minus, subtraction_10_rest);
-    expect_number!(10, subtraction_10_rest.left_side());
+#[test]
+#[should_panic(expected = "1:8: expected '{', found ';'")]
+fn while_without_a_body() {
+    parse(lex_program("while 1;").unwrap());
+}
+
+#[test]
+#[should_panic(expected = "1:9: expected an expression, found 'while'")]
+fn while_as_a_value() {
+    parse(lex_program("let a = while 1 {}").unwrap());
+}
+
+#[test]
+#[should_panic(expected = "1:1: unexpected '{'")]
+fn block_outside_of_a_function() {
+    parse(lex_program("{ return 1; }").unwrap());
+}
 
-    let subtraction_2_rest = subtraction_10_rest.right;
-    expect_infix!(TokenType::Minus, subtraction_2_rest);
-    expect_number!(2, *subtraction_2_rest.left);
+#[test]
+#[should_panic(expected = "1:7: unexpected '{'")]
+fn block_as_an_operand() {
+    parse(lex_program("2.5 / { }").unwrap());
+}
+
+fn call(name: &str, arguments: Vec<Expr>) -> Expr {
+    Expr::Call { name: name.into(), arguments, span: Span::default() }
+}
+
+#[test]
+fn call_expressions() {
+    assert_eq!(call("main", vec![]), parse_expression("main();"));
+    assert_eq!(
+        infix(
+            TokenType::Plus,
+            call("add", vec![number(1), infix(TokenType::Asterisk, identifier("x"), number(2))]),
+            number(3)
+        ),
+        parse_expression("add(1, x * 2) + 3;")
+    );
+    assert_eq!(
+        prefix(TokenType::Minus, call("f", vec![call("g", vec![identifier("x")]), grouping(number(4))])),
+        parse_expression("-f(g(x), (4));")
+    );
+}
+
+#[test]
+fn calls_and_declarations_are_told_apart() {
+    assert_eq!(
+        vec![
+            function("square", &["x"], vec![]),
+            Stmt::Let { name: "y".into(), value: call("square", vec![number(4)]), span: Span::default() },
+            Stmt::Expression { expr: call("square", vec![identifier("y")]) },
+        ],
+        parse_statements("square(x) {} let y = square(4); square(y);")
+    );
+}
+
+#[test]
+fn call_span_covers_the_arguments() {
+    let tokens = lex_program("x = max(1, 2) ;").unwrap();
+    let statements = parse(tokens);
+
+    let span = statements[0].span();
+    assert_eq!((0, 13), (span.offset, span.len));
+}
 
-    let subtraction_4_1 = *subtraction_2_rest.right;
-    expect_infix!(TokenType::Minus, subtraction_4_1);
-    expect_number!(4, *subtraction_4_1.left);
-    expect_number!(1, *subtraction_4_1.right);
+#[test]
+#[should_panic(expected = "1:5: expected ',' or ')' after an argument, found '2'")]
+fn arguments_without_commas() {
+    parse(lex_program("f(1 2);").unwrap());
+}
+
+#[test]
+#[should_panic(expected = "1:4: expected an expression after ','")]
+fn call_cut_short() {
+    parse(lex_program("f(1,").unwrap());
+}
+
+#[test]
+#[should_panic(expected = "1:5: expected an expression, found ')'")]
+fn trailing_comma_in_arguments() {
+    parse(lex_program("f(1,);").unwrap());
+}
+
+#[test]
+#[should_panic(expected = "1:1: expected '(' after 'print'")]
+fn print_at_end_of_file() {
+    parse(lex_program("print").unwrap());
+}
+
+#[test]
+#[should_panic(expected = "1:7: expected ')' to close print after '1'")]
+fn print_cut_short() {
+    parse(lex_program("print(1").unwrap());
+}
+
+#[test]
+#[should_panic(expected = "1:7: expected '(', found '1'")]
+fn print_without_parentheses() {
+    parse(lex_program("print 1;").unwrap());
+}
+
+#[test]
+#[should_panic(expected = "1:1: 'else' is a reserved keyword")]
+fn reserved_keyword_as_a_statement() {
+    parse(lex_program("else;").unwrap());
+}
+
+#[test]
+#[should_panic(expected = "1:9: 'struct' is a reserved keyword")]
+fn reserved_keyword_as_a_value() {
+    parse(lex_program("let a = struct;").unwrap());
+}
+
+#[test]
+#[should_panic(expected = "1:1: 'if' is not supported yet")]
+fn if_is_not_supported_yet() {
+    parse(lex_program("if 1 { return 2; }").unwrap());
+}
+
+#[test]
+#[should_panic(expected = "1:16: empty interpolation")]
+fn empty_interpolation() {
+    parse(lex_program(r#"print("a {1} b {} c");"#).unwrap());
 }
-*/
\ No newline at end of file
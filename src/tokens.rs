@@ -1,22 +1,237 @@
 use std::fmt;
-use crate::tokens::TokenType::{Identifier, KeywordFn, KeywordIf, KeywordLet, KeywordPrint, KeywordReturn, KeywordWhile};
+use std::hash::{Hash, Hasher};
+use std::ops::{Deref, Range};
+use std::rc::Rc;
+use crate::tokens::TokenType::{
+    Identifier, KeywordBreak, KeywordConst, KeywordContinue, KeywordElse, KeywordExtern, KeywordFalse, KeywordFn,
+    KeywordFor, KeywordIf, KeywordImport, KeywordIn, KeywordLet, KeywordPrint, KeywordReturn, KeywordStruct,
+    KeywordTrue, KeywordWhile,
+};
 
 #[rustfmt::skip]
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub enum TokenType {
-    Identifier, String, Number,
-    Assignment, Plus, Minus, Asterisk,
-    Semicolon,
+    Identifier, String, RawString, Number, Float, Char,
+    StringHead, StringMiddle, StringTail,
+    Assignment, Plus, Minus, Asterisk, Slash, Percent,
+    Comma, Semicolon,
     Bang, Equals, Unequal, Lt, Gt, Lteq, Gteq,
+    And, Or, Ampersand, Pipe, Caret, Tilde, Lshift, Rshift,
     Lparen, Rparen, Lbrace, Rbrace,
     KeywordIf, KeywordPrint, KeywordReturn, KeywordWhile, KeywordLet, KeywordFn,
+    KeywordTrue, KeywordFalse,
+    KeywordElse, KeywordFor, KeywordIn, KeywordBreak, KeywordContinue,
+    KeywordConst, KeywordImport, KeywordExtern, KeywordStruct,
+    Eof,
     None,
 }
 
+impl TokenType {
+    /// How an operator is written in the source, for error messages.
+    pub fn operator_text(self) -> &'static str {
+        match self {
+            TokenType::Assignment => "=",
+            TokenType::Plus => "+",
+            TokenType::Minus => "-",
+            TokenType::Asterisk => "*",
+            TokenType::Slash => "/",
+            TokenType::Percent => "%",
+            TokenType::Bang => "!",
+            TokenType::Equals => "==",
+            TokenType::Unequal => "!=",
+            TokenType::Lt => "<",
+            TokenType::Gt => ">",
+            TokenType::Lteq => "<=",
+            TokenType::Gteq => ">=",
+            TokenType::And => "&&",
+            TokenType::Or => "||",
+            TokenType::Ampersand => "&",
+            TokenType::Pipe => "|",
+            TokenType::Caret => "^",
+            TokenType::Tilde => "~",
+            TokenType::Lshift => "<<",
+            TokenType::Rshift => ">>",
+            token_type => panic!("{:?} is not an operator", token_type),
+        }
+    }
+}
+
+/// Location of a piece of source code. `offset` and `len` are in bytes,
+/// `line` and `column` are 1-based, with columns counted in characters.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Span {
+    pub file: Rc<str>,
+    pub offset: usize,
+    pub len: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Span {
+    /// Returns a span starting where `self` starts and ending where `end` ends.
+    pub fn to(&self, end: &Span) -> Span {
+        Span {
+            file: self.file.clone(),
+            offset: self.offset,
+            len: (end.offset + end.len).saturating_sub(self.offset),
+            line: self.line,
+            column: self.column,
+        }
+    }
+
+    /// Returns the span of `text[range]`, where `text` is the source covered by `self`.
+    pub fn within(&self, text: &str, range: Range<usize>) -> Span {
+        let mut line = self.line;
+        let mut column = self.column;
+        for c in text[..range.start].chars() {
+            if c == '\n' {
+                line += 1;
+                column = 1;
+            } else {
+                column += 1;
+            }
+        }
+        Span {
+            file: self.file.clone(),
+            offset: self.offset + range.start,
+            len: range.end - range.start,
+            line,
+            column,
+        }
+    }
+
+    fn write_json(&self, out: &mut String) {
+        out.push_str("{\"file\":");
+        write_json_string(out, &self.file);
+        out.push_str(&format!(
+            ",\"offset\":{},\"len\":{},\"line\":{},\"column\":{}}}",
+            self.offset, self.len, self.line, self.column
+        ));
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.file.is_empty() {
+            write!(f, "{}:{}", self.line, self.column)
+        } else {
+            write!(f, "{}:{}:{}", self.file, self.line, self.column)
+        }
+    }
+}
+
+/// An immutable, cheaply cloned string. The lexer hands out slices of one
+/// shared copy of the source, so tokens never copy or allocate their text.
+#[derive(Clone)]
+pub struct SharedStr {
+    text: Rc<str>,
+    range: Range<usize>,
+}
+
+impl SharedStr {
+    /// `text[range]`, sharing `text` rather than copying it.
+    pub fn slice(text: &Rc<str>, range: Range<usize>) -> SharedStr {
+        assert!(text.is_char_boundary(range.start) && text.is_char_boundary(range.end));
+        SharedStr { text: text.clone(), range }
+    }
+}
+
+impl Default for SharedStr {
+    fn default() -> SharedStr {
+        SharedStr::from("")
+    }
+}
+
+impl Deref for SharedStr {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.text[self.range.clone()]
+    }
+}
+
+impl From<&str> for SharedStr {
+    fn from(text: &str) -> SharedStr {
+        SharedStr { text: Rc::from(text), range: 0..text.len() }
+    }
+}
+
+impl From<String> for SharedStr {
+    fn from(text: String) -> SharedStr {
+        let range = 0..text.len();
+        SharedStr { text: Rc::from(text), range }
+    }
+}
+
+impl PartialEq for SharedStr {
+    fn eq(&self, other: &SharedStr) -> bool {
+        **self == **other
+    }
+}
+
+impl Eq for SharedStr {}
+
+impl Hash for SharedStr {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (**self).hash(state)
+    }
+}
+
+impl PartialEq<str> for SharedStr {
+    fn eq(&self, other: &str) -> bool {
+        &**self == other
+    }
+}
+
+impl PartialEq<&str> for SharedStr {
+    fn eq(&self, other: &&str) -> bool {
+        &**self == *other
+    }
+}
+
+impl PartialEq<SharedStr> for &str {
+    fn eq(&self, other: &SharedStr) -> bool {
+        *self == &**other
+    }
+}
+
+impl fmt::Debug for SharedStr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", &**self)
+    }
+}
+
+impl fmt::Display for SharedStr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum TriviaKind {
+    Whitespace,
+    LineComment,
+    BlockComment,
+}
+
+/// Source text that carries no meaning for the parser. Only kept when lexing
+/// with trivia, see `Lexer::with_trivia`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Trivia {
+    pub kind: TriviaKind,
+    pub text: SharedStr,
+    pub span: Span,
+}
+
 #[derive(PartialEq, Clone)]
 pub struct Token {
     pub token_type: TokenType,
-    pub literal: String,
+    pub literal: SharedStr,
+    pub span: Span,
+    /// Trivia between the previous token's trailing trivia and this token.
+    pub leading_trivia: Vec<Trivia>,
+    /// Trivia after this token, up to and including the end of its line.
+    pub trailing_trivia: Vec<Trivia>,
 }
 
 impl fmt::Debug for Token {
@@ -27,18 +242,24 @@ impl fmt::Debug for Token {
 
 impl Token {
 
-    pub fn new(token_type: TokenType, literal: String) -> Token {
+    pub fn new(token_type: TokenType, literal: impl Into<SharedStr>, span: Span) -> Token {
         Token {
             token_type,
-            literal: literal,
+            literal: literal.into(),
+            span,
+            leading_trivia: Vec::new(),
+            trailing_trivia: Vec::new(),
         }
     }
 
-    pub fn new_with_identifier(literal: &str) -> Token {
+    pub fn new_with_identifier(literal: &str, span: Span) -> Token {
         let token_type = Self::type_given_identifier(literal);
         Token {
             token_type,
-            literal: literal.to_string()
+            literal: literal.into(),
+            span,
+            leading_trivia: Vec::new(),
+            trailing_trivia: Vec::new(),
         }
     }
 
@@ -50,14 +271,105 @@ impl Token {
             "while" => KeywordWhile,
             "let" => KeywordLet,
             "fn" => KeywordFn,
+            "true" => KeywordTrue,
+            "false" => KeywordFalse,
+            "else" => KeywordElse,
+            "for" => KeywordFor,
+            "in" => KeywordIn,
+            "break" => KeywordBreak,
+            "continue" => KeywordContinue,
+            "const" => KeywordConst,
+            "import" => KeywordImport,
+            "extern" => KeywordExtern,
+            "struct" => KeywordStruct,
             _ => Identifier,
         }
     }
 
+    /// The token as a single line JSON object with its type, literal and
+    /// span, as printed by `--emit=tokens`.
+    pub fn to_json(&self) -> String {
+        let mut out = String::from("{\"type\":");
+        write_json_string(&mut out, &format!("{:?}", self.token_type));
+        out.push_str(",\"literal\":");
+        write_json_string(&mut out, &self.literal);
+        out.push_str(",\"span\":");
+        self.span.write_json(&mut out);
+        out.push('}');
+        out
+    }
+
+    /// Decoded value of a string literal, with its escape sequences applied.
+    /// For the pieces of an interpolated string, this is the text between the
+    /// quote or brace delimiters. Raw strings are taken as they are.
+    pub fn to_unescaped(&self) -> String {
+        match self.token_type {
+            TokenType::RawString => {
+                let hashes = self.literal[1..].find('"').unwrap();
+                self.literal[hashes + 2..self.literal.len() - hashes - 1].to_string()
+            }
+            TokenType::String | TokenType::StringHead | TokenType::StringMiddle | TokenType::StringTail => {
+                let contents = &self.literal[1..self.literal.len() - 1];
+                crate::lexer::unescape(contents, |_, _| {})
+            }
+            _ => panic!("attempting to call to_unescaped on a non string")
+        }
+    }
+
+    /// Decoded value of a character literal.
+    pub fn to_char(&self) -> char {
+        match self.token_type {
+            TokenType::Char => {
+                let contents = &self.literal[1..self.literal.len() - 1];
+                crate::lexer::unescape(contents, |_, _| {}).chars().next().unwrap()
+            }
+            _ => panic!("attempting to call to_char on a non char")
+        }
+    }
+
+    /// Value of an integer literal. The lexer lets 2^63 through for
+    /// `to_negated_numeric`, so it is reported here as overflowing.
     pub fn to_numeric(&self) -> i64 {
         match self.token_type {
-            TokenType::Number => self.literal.parse::<i64>().unwrap(),
+            TokenType::Number => crate::lexer::parse_integer(&self.literal).unwrap_or_else(|kind| {
+                panic!("{}", crate::lexer::LexError { kind, span: self.span.clone() })
+            }),
             _ => panic!("attempting to call to_numeric on a non number")
         }
     }
+
+    /// Value of an integer literal after a minus sign, which can be `i64::MIN`.
+    pub fn to_negated_numeric(&self) -> i64 {
+        match self.token_type {
+            TokenType::Number => {
+                let magnitude = crate::lexer::parse_integer_magnitude(&self.literal)
+                    .expect("number token was validated by the lexer");
+                (magnitude as i64).wrapping_neg()
+            }
+            _ => panic!("attempting to call to_negated_numeric on a non number")
+        }
+    }
+
+    pub fn to_float(&self) -> f64 {
+        match self.token_type {
+            TokenType::Float => crate::lexer::parse_float(&self.literal).expect("float token was validated by the lexer"),
+            _ => panic!("attempting to call to_float on a non float")
+        }
+    }
+}
+
+fn write_json_string(out: &mut String, value: &str) {
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
 }